@@ -0,0 +1,130 @@
+//! Runs the same DNA through every `Backend`, reporting time and peak heap
+//! usage. Set `ENDO_DNA` to a file to run that instead of the synthetic
+//! programs, for `ENDO_STEPS` steps.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use endo::backend::Backend;
+use endo::dna::DNA;
+use endo::execute::{asnat, step_backend};
+use endo::generate;
+use endo::piece_table::PieceTable;
+
+/// Tracks the bytes allocated now and at most since the last `reset_peak`.
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+        System.dealloc(p, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn reset_peak() -> usize {
+    let now = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(now, Ordering::Relaxed);
+    now
+}
+
+/// Length of the DNA the instructions run in front of.
+const TAIL_LEN: usize = 1_000_000;
+
+/// Number of instructions in a synthetic program.
+const STEPS: usize = 2000;
+
+/// Templates applied to the bases after the program. Group 0 is the rest of
+/// the program, which is put back first, and group 1 is the next 8 bases.
+const WORKLOADS: &[(&str, &str)] = &[
+    // `n_0 n_1`, rewriting the bases with themselves
+    ("rewrite", "IFPP IFPCP"),
+    // `n_0 n_1 n_1`, so the DNA grows
+    ("duplicate", "IFPP IFPCP IFPCP"),
+    // `n_0 n_1^1`, quoting the bases
+    ("quote", "IFPP IFCPCP"),
+];
+
+/// `STEPS` instructions, each with the pattern `(!n)(!8)` where `n` skips the
+/// rest of the program, followed by `tail`.
+fn program(template: &str, tail: &str) -> DNA {
+    let mut rest = String::new();
+    for _ in 0..STEPS {
+        let pattern = format!("IIP IP{} IIC IIP IP{} IIC IIC", asnat(rest.len()), asnat(8));
+        let instruction: String = format!("{}{}IIC", pattern, template)
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        rest = instruction + &rest;
+    }
+    (rest + tail).as_str().into()
+}
+
+struct Report {
+    time: Duration,
+    peak: usize,
+    len: usize,
+}
+
+fn run<B: Backend>(dna: &DNA, steps: usize) -> Report {
+    let before = reset_peak();
+    let start = Instant::now();
+    let mut d = B::from_dna(dna.clone());
+    for _ in 0..steps {
+        if step_backend(&mut d, &mut |_| ()).is_err() {
+            break;
+        }
+    }
+    let time = start.elapsed();
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    Report { time, peak, len: d.len() }
+}
+
+fn compare(name: &str, dna: &DNA, steps: usize) {
+    let reports = [
+        ("im::Vector", run::<DNA>(dna, steps)),
+        ("piece table", run::<PieceTable>(dna, steps)),
+    ];
+    for (backend, r) in reports.iter() {
+        println!(
+            "{:<12} {:<12} {:>12.3?} {:>10} KiB peak {:>10} bases",
+            name,
+            backend,
+            r.time,
+            r.peak / 1024,
+            r.len
+        );
+    }
+}
+
+fn main() {
+    if let Ok(path) = std::env::var("ENDO_DNA") {
+        let text = std::fs::read_to_string(&path).expect("Unable to read ENDO_DNA");
+        let steps = std::env::var("ENDO_STEPS").map_or(10_000, |s| s.parse().expect("Bad ENDO_STEPS"));
+        compare(&path, &text.as_str().into(), steps);
+        return;
+    }
+    let tail = "ICFP".repeat(TAIL_LEN / 4);
+    for (name, template) in WORKLOADS {
+        compare(name, &program(template, &tail), STEPS);
+    }
+    // Random instructions, on data that stays short
+    let config = generate::Config { instructions: STEPS, ..generate::Config::default() };
+    let generated = generate::program(0, &config);
+    compare("generated", &generated.dna, generated.steps);
+}
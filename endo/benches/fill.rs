@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use endo::render::{Bitmap, Pixel, HEIGHT, WIDTH};
+
+const WALL: Pixel = Pixel { r: 255, g: 255, b: 255, a: 255 };
+const RED: Pixel = Pixel { r: 255, g: 0, b: 0, a: 255 };
+
+/// Walls on every other row, open at alternating ends, so the fill has to
+/// wind through the whole canvas one row at a time.
+fn serpentine() -> Bitmap {
+    let mut bitmap = Bitmap::default();
+    for y in (1..HEIGHT).step_by(2) {
+        let gap = if y % 4 == 1 { WIDTH - 1 } else { 0 };
+        for x in (0..WIDTH).filter(|&x| x != gap) {
+            bitmap.set(x, y, WALL);
+        }
+    }
+    bitmap
+}
+
+/// Walls on every other pixel of every other row, so spans are short and
+/// every row seeds many spans in the next.
+fn lattice() -> Bitmap {
+    let mut bitmap = Bitmap::default();
+    for y in (1..HEIGHT).step_by(2) {
+        for x in (1..WIDTH).step_by(2) {
+            bitmap.set(x, y, WALL);
+        }
+    }
+    bitmap
+}
+
+fn bench_fill(c: &mut Criterion) {
+    let cases = [
+        ("fill_empty", Bitmap::default()),
+        ("fill_serpentine", serpentine()),
+        ("fill_lattice", lattice()),
+    ];
+    for (name, bitmap) in cases.iter() {
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let mut bitmap = bitmap.clone();
+                bitmap.fill(0, 0, RED);
+                bitmap
+            })
+        });
+    }
+}
+
+criterion_group!(benches, bench_fill);
+criterion_main!(benches);
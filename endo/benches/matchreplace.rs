@@ -0,0 +1,57 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use endo::dna::DNA;
+use endo::execute::step;
+
+/// Length of the DNA the instructions run in front of.
+const TAIL_LEN: usize = 1_000_000;
+
+/// Number of instructions in a long run.
+const STEPS: usize = 1000;
+
+/// Pattern `(!2)` and template `n_0^0`, rewriting two bases with themselves.
+const INSTRUCTION: &str = "IIPIPICPIICIIC IFPPIIC";
+
+fn tail() -> String {
+    "ICFP".repeat(TAIL_LEN / 4)
+}
+
+fn bench_splice(c: &mut Criterion) {
+    let dna: DNA = tail().as_str().into();
+    let prefix: DNA = "CFPICFPI".into();
+
+    c.bench_function("splice", |b| {
+        b.iter(|| {
+            let mut d = dna.clone();
+            d.splice(black_box(100), prefix.clone());
+            d
+        })
+    });
+
+    c.bench_function("subseq_assign_concat", |b| {
+        b.iter(|| {
+            let mut d = dna.clone();
+            let tail = d.subseq(black_box(100), d.len());
+            d.assign(prefix.clone());
+            d.concat(tail);
+            d
+        })
+    });
+}
+
+fn bench_long_run(c: &mut Criterion) {
+    let dna: DNA = (INSTRUCTION.repeat(STEPS) + &tail()).as_str().into();
+
+    c.bench_function("long_run", |b| {
+        b.iter(|| {
+            let mut d = dna.clone();
+            for _ in 0..STEPS {
+                step(&mut d, &mut |_| (), &mut ()).unwrap();
+            }
+            d
+        })
+    });
+}
+
+criterion_group!(benches, bench_splice, bench_long_run);
+criterion_main!(benches);
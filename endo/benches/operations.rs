@@ -0,0 +1,114 @@
+//! The operations on `DNA` that execution is made of, one by one, and the
+//! first steps of a real run. The run uses the DNA in the file `ENDO_DNA`, by
+//! default `endo.dna`, for `ENDO_STEPS` steps, and is skipped if there is no
+//! such file.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use endo::dna::{Base, DNA};
+use endo::execute::{matchreplace, protect, quote, step, PItem, TItem};
+
+/// Length of the random DNA searched and sliced.
+const LEN: usize = 1_000_000;
+
+/// Where in the random DNA the needle is found.
+const NEEDLE_AT: usize = 900_000;
+
+fn random(len: usize) -> DNA {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..len).map(|_| Base::ALL[rng.gen_range(0..4)]).collect()
+}
+
+fn bench_find_first(c: &mut Criterion) {
+    let dna = random(LEN);
+    let mut group = c.benchmark_group("find_first");
+    for &len in &[4, 16, 64] {
+        let needle = dna.subseq(NEEDLE_AT, NEEDLE_AT + len);
+        group.bench_with_input(BenchmarkId::new("find_first", len), &needle, |b, needle| {
+            b.iter(|| dna.find_first(needle, black_box(0)))
+        });
+        group.bench_with_input(BenchmarkId::new("find_first_jonas", len), &needle, |b, needle| {
+            b.iter(|| dna.find_first_jonas(needle, black_box(0)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_subseq_concat(c: &mut Criterion) {
+    let dna = random(LEN);
+    c.bench_function("subseq", |b| b.iter(|| dna.subseq(black_box(1000), black_box(LEN - 1000))));
+    let halves = (dna.subseq(0, LEN / 2), dna.subseq(LEN / 2, LEN));
+    c.bench_function("concat", |b| {
+        b.iter(|| {
+            let mut d = halves.0.clone();
+            d.concat(halves.1.clone());
+            d
+        })
+    });
+}
+
+fn bench_quote_protect(c: &mut Criterion) {
+    let dna = random(1000);
+    c.bench_function("quote", |b| b.iter(|| quote(dna.clone())));
+    let mut group = c.benchmark_group("protect");
+    for &level in &[1, 2, 4] {
+        group.bench_with_input(BenchmarkId::from_parameter(level), &level, |b, &level| {
+            b.iter(|| protect(level, dna.clone()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_matchreplace(c: &mut Criterion) {
+    let dna = random(LEN);
+    let needle = dna.subseq(NEEDLE_AT, NEEDLE_AT + 16);
+    // `(?needle)!4`, replaced by the group and its length
+    let search = [PItem::Open(), PItem::Search(needle), PItem::Close(), PItem::Skip(4)];
+    let search_template = [TItem::Ref { n: 0, l: 0 }, TItem::RefLen(0)];
+    // `(!1000)`, replaced by the group quoted twice
+    let quote = [PItem::Open(), PItem::Skip(1000), PItem::Close()];
+    let quote_template = [TItem::Ref { n: 0, l: 2 }];
+    let cases: [(&str, &[PItem], &[TItem]); 2] =
+        [("matchreplace_search", &search, &search_template), ("matchreplace_quote", &quote, &quote_template)];
+    for (name, pattern, template) in cases.iter() {
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                let mut d = dna.clone();
+                matchreplace(&mut d, pattern, template, &mut ());
+                d
+            })
+        });
+    }
+}
+
+fn bench_first_steps(c: &mut Criterion) {
+    let path = std::env::var("ENDO_DNA").unwrap_or_else(|_| "endo.dna".to_string());
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Skipping the run of {}: {}", path, e);
+            return;
+        }
+    };
+    let steps: u64 = std::env::var("ENDO_STEPS").map_or(1000, |s| s.parse().expect("Bad ENDO_STEPS"));
+    let dna: DNA = text.as_str().into();
+    let mut group = c.benchmark_group("run");
+    group.sample_size(10);
+    group.bench_function(BenchmarkId::new("first_steps", steps), |b| {
+        b.iter(|| {
+            let mut d = dna.clone();
+            for _ in 0..steps {
+                if step(&mut d, &mut |_| (), &mut ()).is_err() {
+                    break;
+                }
+            }
+            d
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_find_first, bench_subseq_concat, bench_quote_protect, bench_matchreplace, bench_first_steps);
+criterion_main!(benches);
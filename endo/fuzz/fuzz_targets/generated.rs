@@ -0,0 +1,26 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use endo::backend::Backend;
+use endo::execute::{step, step_backend};
+use endo::generate::{program, Config};
+use endo::piece_table::PieceTable;
+
+// Run with `cargo fuzz run generated`. Unlike `step`, the programs terminate,
+// so each input is a seed checked against every backend to the end.
+fuzz_target!(|data: &[u8]| {
+    let mut seed = [0; 8];
+    let n = data.len().min(8);
+    seed[..n].copy_from_slice(&data[..n]);
+    let p = program(u64::from_le_bytes(seed), &Config::default());
+    let mut dna = p.dna;
+    let mut table = PieceTable::from_dna(dna.clone());
+    let (mut rna, mut table_rna) = (vec![], vec![]);
+    for _ in 0..p.steps {
+        step(&mut dna, &mut |r| rna.push(r), &mut ()).expect("A generated program finished early");
+        step_backend(&mut table, &mut |r| table_rna.push(r)).expect("A generated program finished early");
+    }
+    assert!(dna.is_empty());
+    assert_eq!(table.to_dna(), dna);
+    assert_eq!(table_rna, rna);
+});
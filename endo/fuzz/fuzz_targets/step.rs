@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use endo::dna::DNA;
+use endo::execute::step;
+
+/// Bound on the number of steps, since DNA need not terminate.
+const MAX_STEPS: usize = 16;
+
+// Run with `cargo fuzz run step`. Note that deep protection of a non-empty
+// group legitimately takes forever, so libFuzzer may report timeouts.
+fuzz_target!(|data: &[u8]| {
+    let s: String = data.iter().map(|b| ['I', 'C', 'F', 'P'][(b % 4) as usize]).collect();
+    let mut dna = DNA::from(s.as_str());
+    for _ in 0..MAX_STEPS {
+        if step(&mut dna, &mut |_| (), &mut ()).is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,350 @@
+//! A text form of DNA instructions, for reading code in the DNA and writing
+//! prefixes by hand. An instruction is a line with the pattern, `=>`, and the
+//! template:
+//!
+//! ```text
+//! ( ?IFPP ) F => $0 P
+//! ```
+//!
+//! In patterns, bases stand for themselves, `!n` skips `n` bases, `?s`
+//! searches for `s`, and `(` and `)` delimit groups. In templates, `$n` and
+//! `$n^l` insert group `n` quoted `l` times, and `|n|` its length. In both,
+//! `rna(s)` emits the RNA `s` while decoding. `#` starts a comment, and an
+//! offset like `123:` in front of an instruction is ignored, so disassembled
+//! code can be assembled again.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::dna::{Base, DNA};
+use crate::execute::{asnat, pattern_items, quote, template_items, Finish, PItem, TItem};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Part<T> {
+    Item(T),
+    /// RNA emitted where it is decoded, between items.
+    Rna(DNA),
+}
+
+/// A pattern and a template as encoded in the DNA, RNA included.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    pub pattern: Vec<Part<PItem>>,
+    pub template: Vec<Part<TItem>>,
+}
+
+impl Instruction {
+    /// An instruction without RNA.
+    pub fn new(pattern: &[PItem], template: &[TItem]) -> Self {
+        Instruction {
+            pattern: pattern.iter().cloned().map(Part::Item).collect(),
+            template: template.iter().cloned().map(Part::Item).collect(),
+        }
+    }
+
+    /// Appends the encoding to `out`. Where the specification allows a choice
+    /// of bases, the ones of its example prefix are used: `IIC` to end groups,
+    /// patterns and templates, `IFF` to start a search and `IP` a reference.
+    pub fn encode(&self, out: &mut DNA) {
+        let bases = |s: &str| s.parse::<DNA>().unwrap();
+        for part in &self.pattern {
+            match part {
+                Part::Item(PItem::Base(b)) => out.concat(quoted(*b)),
+                Part::Item(PItem::Skip(n)) => {
+                    out.concat(bases("IP"));
+                    out.concat(asnat(*n));
+                }
+                Part::Item(PItem::Search(s)) => {
+                    out.concat(bases("IFF"));
+                    out.concat(quote(s.clone()));
+                }
+                Part::Item(PItem::Open()) => out.concat(bases("IIP")),
+                Part::Item(PItem::Close()) => out.concat(bases("IIC")),
+                Part::Rna(r) => {
+                    out.concat(bases("III"));
+                    out.concat(r.clone());
+                }
+            }
+        }
+        out.concat(bases("IIC"));
+        for part in &self.template {
+            match part {
+                Part::Item(TItem::Base(b)) => out.concat(quoted(*b)),
+                Part::Item(TItem::Ref { n, l }) => {
+                    out.concat(bases("IP"));
+                    out.concat(asnat(*l));
+                    out.concat(asnat(*n));
+                }
+                Part::Item(TItem::RefLen(n)) => {
+                    out.concat(bases("IIP"));
+                    out.concat(asnat(*n));
+                }
+                Part::Rna(r) => {
+                    out.concat(bases("III"));
+                    out.concat(r.clone());
+                }
+            }
+        }
+        out.concat(bases("IIC"));
+    }
+}
+
+fn quoted(b: Base) -> DNA {
+    quote(std::iter::once(b).collect())
+}
+
+/// Writes the parts separated by spaces, with runs of bases as one word.
+fn write_parts<T>(
+    f: &mut fmt::Formatter<'_>,
+    parts: &[Part<T>],
+    base: impl Fn(&T) -> Option<Base>,
+    item: impl Fn(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+) -> fmt::Result {
+    let mut in_bases = false;
+    for (i, part) in parts.iter().enumerate() {
+        let b = match part {
+            Part::Item(t) => base(t),
+            Part::Rna(_) => None,
+        };
+        if i > 0 && !(in_bases && b.is_some()) {
+            write!(f, " ")?;
+        }
+        in_bases = b.is_some();
+        match (part, b) {
+            (_, Some(b)) => write!(f, "{}", char::from(b))?,
+            (Part::Item(t), None) => item(f, t)?,
+            (Part::Rna(r), None) => write!(f, "rna({})", r)?,
+        }
+    }
+    Ok(())
+}
+
+struct PatternText<'a>(&'a [Part<PItem>]);
+
+impl fmt::Display for PatternText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = |p: &PItem| match p {
+            PItem::Base(b) => Some(*b),
+            _ => None,
+        };
+        write_parts(f, self.0, base, |f, p| match p {
+            PItem::Base(_) => unreachable!(),
+            PItem::Skip(n) => write!(f, "!{}", n),
+            PItem::Search(s) => write!(f, "?{}", s),
+            PItem::Open() => write!(f, "("),
+            PItem::Close() => write!(f, ")"),
+        })
+    }
+}
+
+struct TemplateText<'a>(&'a [Part<TItem>]);
+
+impl fmt::Display for TemplateText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = |t: &TItem| match t {
+            TItem::Base(b) => Some(*b),
+            _ => None,
+        };
+        write_parts(f, self.0, base, |f, t| match t {
+            TItem::Base(_) => unreachable!(),
+            TItem::Ref { n, l: 0 } => write!(f, "${}", n),
+            TItem::Ref { n, l } => write!(f, "${}^{}", n, l),
+            TItem::RefLen(n) => write!(f, "|{}|", n),
+        })
+    }
+}
+
+/// `pattern` in the text form of instructions.
+pub fn pattern_text(pattern: &[PItem]) -> String {
+    PatternText(&Instruction::new(pattern, &[]).pattern).to_string()
+}
+
+/// `template` in the text form of instructions.
+pub fn template_text(template: &[TItem]) -> String {
+    TemplateText(&Instruction::new(&[], template).template).to_string()
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (p, t) = (PatternText(&self.pattern), TemplateText(&self.template));
+        match (self.pattern.is_empty(), self.template.is_empty()) {
+            (true, true) => write!(f, "=>"),
+            (true, false) => write!(f, "=> {}", t),
+            (false, true) => write!(f, "{} =>", p),
+            (false, false) => write!(f, "{} => {}", p, t),
+        }
+    }
+}
+
+/// Decodes the instruction at `pos` of `dna`, and returns it with the position
+/// after it.
+pub fn disassemble(dna: &DNA, pos: usize) -> Result<(Instruction, usize), Finish> {
+    let mut c = dna.cursor(pos);
+    let pattern = RefCell::new(vec![]);
+    pattern_items(
+        &mut c,
+        &mut |r| pattern.borrow_mut().push(Part::Rna(r)),
+        &mut |p| pattern.borrow_mut().push(Part::Item(p)),
+    )?;
+    let template = RefCell::new(vec![]);
+    template_items(
+        &mut c,
+        &mut |r| template.borrow_mut().push(Part::Rna(r)),
+        &mut |t| template.borrow_mut().push(Part::Item(t)),
+    )?;
+    let instruction = Instruction { pattern: pattern.into_inner(), template: template.into_inner() };
+    Ok((instruction, c.pos()))
+}
+
+fn parse_number(s: &str, word: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("bad number in {:?}", word))
+}
+
+fn parse_bases(s: &str, word: &str) -> Result<DNA, String> {
+    s.parse().map_err(|e| format!("{} in {:?}", e, word))
+}
+
+/// Parses the RNA and runs of bases common to patterns and templates.
+fn parse_common<T>(word: &str, base: impl Fn(Base) -> T) -> Result<Option<Vec<Part<T>>>, String> {
+    if let Some(rna) = word.strip_prefix("rna(").and_then(|w| w.strip_suffix(')')) {
+        let rna = parse_bases(rna, word)?;
+        if rna.len() != 7 {
+            return Err(format!("RNA is 7 bases, not {} as in {:?}", rna.len(), word));
+        }
+        return Ok(Some(vec![Part::Rna(rna)]));
+    }
+    if word.chars().all(|c| "ICFP".contains(c)) {
+        let bases = parse_bases(word, word)?;
+        return Ok(Some(bases.iter().map(|&b| Part::Item(base(b))).collect()));
+    }
+    Ok(None)
+}
+
+/// Parses an instruction in the format of `Display`. Instructions that would
+/// not decode to themselves are rejected.
+pub fn parse_instruction(line: &str) -> Result<Instruction, String> {
+    let (pattern, template) = line.split_once("=>").ok_or("missing `=>`")?;
+    let mut i = Instruction::default();
+    let mut level = 0;
+    for word in pattern.split_whitespace() {
+        if let Some(parts) = parse_common(word, PItem::Base)? {
+            if let (Some(Part::Item(PItem::Search(_))), Some(Part::Item(_))) = (i.pattern.last(), parts.first()) {
+                return Err(format!("a search can't be followed by the bases {:?}", word));
+            }
+            i.pattern.extend(parts);
+            continue;
+        }
+        let item = match word {
+            "(" => {
+                level += 1;
+                PItem::Open()
+            }
+            ")" if level == 0 => return Err("`)` without `(`".to_string()),
+            ")" => {
+                level -= 1;
+                PItem::Close()
+            }
+            _ => match (word.strip_prefix('!'), word.strip_prefix('?')) {
+                (Some(n), _) => PItem::Skip(parse_number(n, word)?),
+                (_, Some(s)) => PItem::Search(parse_bases(s, word)?),
+                _ => return Err(format!("unknown pattern item {:?}", word)),
+            },
+        };
+        i.pattern.push(Part::Item(item));
+    }
+    if level > 0 {
+        return Err("`(` without `)`".to_string());
+    }
+    for word in template.split_whitespace() {
+        if let Some(parts) = parse_common(word, TItem::Base)? {
+            i.template.extend(parts);
+            continue;
+        }
+        let item = if let Some(r) = word.strip_prefix('$') {
+            match r.split_once('^') {
+                Some((n, l)) => TItem::Ref { n: parse_number(n, word)?, l: parse_number(l, word)? },
+                None => TItem::Ref { n: parse_number(r, word)?, l: 0 },
+            }
+        } else if let Some(n) = word.strip_prefix('|').and_then(|w| w.strip_suffix('|')) {
+            TItem::RefLen(parse_number(n, word)?)
+        } else {
+            return Err(format!("unknown template item {:?}", word));
+        };
+        i.template.push(Part::Item(item));
+    }
+    Ok(i)
+}
+
+/// Assembles a program of an instruction per line. Errors name the line.
+pub fn assemble(text: &str) -> Result<DNA, String> {
+    let mut dna = DNA::default();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        // Skip an offset written by the disassembler
+        let line = match line.split_once(':') {
+            Some((offset, rest)) if offset.trim().parse::<usize>().is_ok() => rest,
+            _ => line,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let i = parse_instruction(line).map_err(|e| format!("line {}: {}", n + 1, e))?;
+        i.encode(&mut dna);
+    }
+    Ok(dna)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// The example prefix of the specification.
+    const EXAMPLE: &str = "IIPIFFCPICICIICPIICIPPPICIIC";
+
+    #[test]
+    fn test_disassemble() {
+        let (i, end) = disassemble(&EXAMPLE.into(), 0).unwrap();
+        assert_eq!(i.to_string(), "( ?IFPP ) F => $0 P");
+        assert_eq!(end, EXAMPLE.len());
+        assert_eq!(assemble(&i.to_string()), Ok(EXAMPLE.into()));
+
+        let (i, _) = disassemble(&"IIIPIPIIIC CIIC IPCPCCP IIPICP IIIPIPIIIP IIC".into(), 0).unwrap();
+        assert_eq!(i.to_string(), "rna(PIPIIIC) I => $3^1 |2| rna(PIPIIIP)");
+        let (i, _) = disassemble(&"IIC IIC".into(), 0).unwrap();
+        assert_eq!(i.to_string(), "=>");
+        assert!(disassemble(&"IIC II".into(), 0).is_err());
+
+        assert_eq!(pattern_text(&[PItem::Skip(2), PItem::Base(Base::I), PItem::Base(Base::C)]), "!2 IC");
+        assert_eq!(template_text(&[TItem::RefLen(0)]), "|0|");
+    }
+
+    #[test]
+    fn test_assemble() {
+        let program = "# Two instructions\n0: ( ?IFPP ) F => $0 P\n\n28: !7 => PP $1^2 |0| # end\n";
+        let dna = assemble(program).unwrap();
+        let (second, end) = disassemble(&dna, EXAMPLE.len()).unwrap();
+        assert_eq!(second.to_string(), "!7 => PP $1^2 |0|");
+        assert_eq!(end, dna.len());
+
+        assert_eq!(assemble("( => P"), Err("line 1: `(` without `)`".to_string()));
+        assert!(assemble(") =>").is_err());
+        assert!(assemble("?IC I =>").is_err());
+        assert!(assemble("!x =>").is_err());
+        assert!(assemble("=> $1^").is_err());
+        assert!(assemble("=> rna(PIP)").is_err());
+        assert!(assemble("I").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_disassemble_assemble(s in "[ICFP]{0,80}") {
+            let dna: DNA = s.as_str().into();
+            if let Ok((i, _)) = disassemble(&dna, 0) {
+                let text = i.to_string();
+                let again = assemble(&text).unwrap();
+                prop_assert_eq!(disassemble(&again, 0).unwrap(), (i, again.len()));
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! The operations `execute` needs from a DNA representation, so that other
+//! data structures than `DNA` can be compared with it.
+
+use crate::dna::{self, Base, DNA};
+
+/// Reads bases from left to right, as `dna::Cursor` does.
+pub trait DnaCursor: Iterator<Item = Base> {
+    /// Number of bases read from the start of the sequence.
+    fn pos(&self) -> usize;
+
+    /// The base `offset` bases ahead, without consuming anything.
+    fn peek(&mut self, offset: usize) -> Option<Base>;
+
+    /// Consumes `count` bases, or fewer at the end.
+    fn advance(&mut self, count: usize);
+
+    /// Consumes and returns the next `count` bases, or fewer at the end.
+    fn read(&mut self, count: usize) -> DNA {
+        let mut d = DNA::default();
+        for _ in 0..count {
+            match self.next() {
+                Some(b) => d.append(b),
+                None => break,
+            }
+        }
+        d
+    }
+}
+
+/// A sequence of bases that can be executed. Sequences are only combined
+/// with sequences derived from the same one with `subseq` or `empty`.
+pub trait Backend: Clone {
+    type Cursor<'a>: DnaCursor
+    where
+        Self: 'a;
+
+    fn from_dna(dna: DNA) -> Self;
+
+    fn to_dna(&self) -> DNA;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A cursor reading from `pos` onwards.
+    fn cursor(&self, pos: usize) -> Self::Cursor<'_>;
+
+    fn at(&self, index: usize) -> Option<Base>;
+
+    /// The least index after an occurrence of `needle` starting at `from` or
+    /// later. Searches with the Knuth-Morris-Pratt algorithm by default.
+    fn find_first(&self, needle: &DNA, from: usize) -> Option<usize> {
+        let needle: Vec<Base> = needle.iter().copied().collect();
+        if from > self.len() {
+            return None;
+        } else if needle.is_empty() {
+            return Some(from);
+        }
+        // `fallback[i]` is the length of the longest proper prefix of
+        // `needle[..=i]` that is also a suffix of it
+        let mut fallback = vec![0; needle.len()];
+        let mut k = 0;
+        for i in 1..needle.len() {
+            while k > 0 && needle[i] != needle[k] {
+                k = fallback[k - 1];
+            }
+            if needle[i] == needle[k] {
+                k += 1;
+            }
+            fallback[i] = k;
+        }
+        let mut matched = 0;
+        let mut c = self.cursor(from);
+        while let Some(b) = c.next() {
+            while matched > 0 && b != needle[matched] {
+                matched = fallback[matched - 1];
+            }
+            if b == needle[matched] {
+                matched += 1;
+            }
+            if matched == needle.len() {
+                return Some(c.pos());
+            }
+        }
+        None
+    }
+
+    /// Indexes are 0-based, and end is not inclusive.
+    fn subseq(&self, start: usize, end: usize) -> Self;
+
+    fn drop(&mut self, count: usize);
+
+    /// Replaces the first `len` bases by `prefix`.
+    fn splice(&mut self, len: usize, prefix: Self);
+
+    /// An empty sequence that can be combined with this one.
+    fn empty(&self) -> Self;
+
+    fn append(&mut self, b: Base);
+
+    fn append_dna(&mut self, d: &DNA);
+
+    fn concat(&mut self, rhs: Self);
+}
+
+impl<'a> DnaCursor for dna::Cursor<'a> {
+    fn pos(&self) -> usize {
+        dna::Cursor::pos(self)
+    }
+
+    fn peek(&mut self, offset: usize) -> Option<Base> {
+        dna::Cursor::peek(self, offset)
+    }
+
+    fn advance(&mut self, count: usize) {
+        dna::Cursor::advance(self, count)
+    }
+
+    fn read(&mut self, count: usize) -> DNA {
+        dna::Cursor::read(self, count)
+    }
+}
+
+impl Backend for DNA {
+    type Cursor<'a> = dna::Cursor<'a>;
+
+    fn from_dna(dna: DNA) -> Self {
+        dna
+    }
+
+    fn to_dna(&self) -> DNA {
+        self.clone()
+    }
+
+    fn len(&self) -> usize {
+        DNA::len(self)
+    }
+
+    fn cursor(&self, pos: usize) -> dna::Cursor<'_> {
+        DNA::cursor(self, pos)
+    }
+
+    fn at(&self, index: usize) -> Option<Base> {
+        DNA::at(self, index)
+    }
+
+    fn find_first(&self, needle: &DNA, from: usize) -> Option<usize> {
+        DNA::find_first(self, needle, from)
+    }
+
+    fn subseq(&self, start: usize, end: usize) -> Self {
+        DNA::subseq(self, start, end)
+    }
+
+    fn drop(&mut self, count: usize) {
+        DNA::drop(self, count)
+    }
+
+    fn splice(&mut self, len: usize, prefix: Self) {
+        DNA::splice(self, len, prefix)
+    }
+
+    fn empty(&self) -> Self {
+        DNA::default()
+    }
+
+    fn append(&mut self, b: Base) {
+        DNA::append(self, b)
+    }
+
+    fn append_dna(&mut self, d: &DNA) {
+        DNA::concat(self, d.clone())
+    }
+
+    fn concat(&mut self, rhs: Self) {
+        DNA::concat(self, rhs)
+    }
+}
@@ -0,0 +1,90 @@
+//! The bucket of colors that determines the pixel drawn, as in figure 20 of
+//! the specification.
+
+use crate::render::Pixel;
+use crate::rna::Color;
+
+/// Only sums and counts are kept, since the order of colors in the bucket
+/// never matters.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Bucket {
+    rgb: [u64; 3],
+    rgb_count: u64,
+    alpha: u64,
+    alpha_count: u64,
+}
+
+impl Bucket {
+    pub fn add(&mut self, c: Color) {
+        match c {
+            Color::Rgb(r, g, b) => {
+                self.rgb[0] += r as u64;
+                self.rgb[1] += g as u64;
+                self.rgb[2] += b as u64;
+                self.rgb_count += 1;
+            }
+            Color::Alpha(a) => {
+                self.alpha += a as u64;
+                self.alpha_count += 1;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Bucket::default();
+    }
+
+    /// The average color, defaulting to black, times the average transparency,
+    /// defaulting to opaque. Both averages and the product are truncated.
+    pub fn current_pixel(&self) -> Pixel {
+        let average = |sum: u64, count: u64, default| sum.checked_div(count).unwrap_or(default);
+        let a = average(self.alpha, self.alpha_count, 255);
+        let c = |i: usize| (average(self.rgb[i], self.rgb_count, 0) * a / 255) as u8;
+        Pixel { r: c(0), g: c(1), b: c(2), a: a as u8 }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rna::*;
+
+    fn pixel(colors: &[(Color, usize)]) -> Pixel {
+        let mut bucket = Bucket::default();
+        for &(c, n) in colors {
+            (0..n).for_each(|_| bucket.add(c));
+        }
+        bucket.current_pixel()
+    }
+
+    #[test]
+    fn test_current_pixel() {
+        // The examples below figure 20
+        let p = |r, g, b, a| Pixel { r, g, b, a };
+        assert_eq!(pixel(&[(TRANSPARENT, 1), (OPAQUE, 2)]), p(0, 0, 0, 170));
+        assert_eq!(pixel(&[(BLACK, 1), (YELLOW, 1), (CYAN, 1)]), p(85, 170, 85, 255));
+        assert_eq!(pixel(&[(YELLOW, 1), (TRANSPARENT, 1), (OPAQUE, 1)]), p(127, 127, 0, 127));
+        assert_eq!(
+            pixel(&[
+                (BLACK, 18),
+                (RED, 7),
+                (MAGENTA, 39),
+                (WHITE, 10),
+                (OPAQUE, 3),
+                (TRANSPARENT, 1),
+            ]),
+            p(143, 25, 125, 191)
+        );
+    }
+
+    #[test]
+    fn test_empty_and_clear() {
+        let mut bucket = Bucket::default();
+        assert_eq!(bucket.current_pixel(), Pixel { r: 0, g: 0, b: 0, a: 255 });
+        bucket.add(WHITE);
+        bucket.add(TRANSPARENT);
+        assert_eq!(bucket.current_pixel(), Pixel::default());
+        bucket.clear();
+        assert_eq!(bucket, Bucket::default());
+    }
+}
@@ -0,0 +1,160 @@
+//! Cache of decoded patterns and templates. Endo keeps copying the same genes
+//! to the front of the DNA, so the same code is decoded over and over again.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::dna::DNA;
+use crate::execute::{PItem, TItem};
+
+/// Number of leading bases hashed for the lookup. The shortest code, an empty
+/// pattern and template, is six bases long.
+const KEY_LEN: usize = 16;
+
+/// Entries kept for each key, most recently used first.
+const ENTRIES_PER_KEY: usize = 8;
+
+/// The result of decoding the code at the front of the DNA.
+#[derive(Debug)]
+pub struct Entry {
+    /// The bases the pattern and template were decoded from.
+    pub code: DNA,
+    pub pattern: Vec<PItem>,
+    pub template: Vec<TItem>,
+    /// RNA emitted while decoding, in order.
+    pub rna: Vec<DNA>,
+}
+
+/// Maps code at the front of the DNA to its decoding. Every entry is checked
+/// against the DNA, so hash collisions only cost time.
+pub struct Cache {
+    capacity: usize,
+    len: usize,
+    entries: HashMap<u64, Vec<Rc<Entry>>>,
+    hits: u64,
+    misses: u64,
+}
+
+/// FNV-1a hash of the first `KEY_LEN` bases.
+fn key(dna: &DNA) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in dna.iter().take(KEY_LEN) {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+impl Cache {
+    /// A cache of at most `capacity` entries. It is emptied when full.
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            capacity: capacity.max(1),
+            len: 0,
+            entries: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The entry for the code at the front of `dna`, if any.
+    pub fn get(&mut self, dna: &DNA) -> Option<Rc<Entry>> {
+        let bucket = self.entries.get_mut(&key(dna));
+        let found = bucket.and_then(|bucket| {
+            let i = bucket.iter().position(|e| dna.starts_with(&e.code))?;
+            // Keep hot entries at the front
+            bucket[..=i].rotate_right(1);
+            Some(bucket[0].clone())
+        });
+        match found {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        found
+    }
+
+    /// Adds `entry`, which was decoded from the front of `dna`.
+    pub fn insert(&mut self, dna: &DNA, entry: Rc<Entry>) {
+        if self.len >= self.capacity {
+            self.entries.clear();
+            self.len = 0;
+        }
+        let bucket = self.entries.entry(key(dna)).or_default();
+        bucket.insert(0, entry);
+        if bucket.len() > ENTRIES_PER_KEY {
+            bucket.pop();
+        } else {
+            self.len += 1;
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Fraction of lookups that hit, or 0 if there were none.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+impl fmt::Display for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "decode cache: {} hits, {} misses, hit rate {:.1}%, {} entries",
+            self.hits,
+            self.misses,
+            100.0 * self.hit_rate(),
+            self.len
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::execute::{execute_cached, execute_monitored};
+
+    #[test]
+    fn test_cache() {
+        let mut cache = Cache::new(10);
+        let entry = |code: &str| {
+            Rc::new(Entry { code: code.into(), pattern: vec![], template: vec![], rna: vec![] })
+        };
+        let dna: DNA = "IIC IIC ICFP ICFP ICFP ICFP".into();
+        assert!(cache.get(&dna).is_none());
+        cache.insert(&dna, entry("IIC IIC ICFP ICFP ICFP IC"));
+        assert_eq!(cache.get(&dna).unwrap().code.len(), 20);
+        // Same key, but the code differs after it
+        assert!(cache.get(&"IIC IIC ICFP ICFP ICFP IF".into()).is_none());
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    }
+
+    #[test]
+    fn test_execute_cached() {
+        // Each instruction is an empty pattern and a template emitting RNA,
+        // so the same code runs again and again
+        let program = "IIC III(PIPIIIC) IIC".repeat(20);
+
+        let mut rna = vec![];
+        execute_monitored(program.as_str().into(), |r| rna.push(r), &mut ());
+        let mut cached_rna = vec![];
+        let mut cache = Cache::new(10);
+        execute_cached(program.as_str().into(), |r| cached_rna.push(r), &mut (), &mut cache);
+
+        assert_eq!(rna.len(), 20);
+        assert_eq!(cached_rna, rna);
+        assert_eq!((cache.hits(), cache.misses()), (19, 2));
+    }
+}
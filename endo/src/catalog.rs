@@ -0,0 +1,157 @@
+//! A catalog of the pages Endo can show, such as help screens, each reached by
+//! putting a prefix in front of the DNA. The catalog is a TOML file:
+//!
+//! ```toml
+//! [[page]]
+//! name = "help"
+//! prefix = "IIPIFFCPICICIICPIICIPPPICIIC"
+//! description = "The repair guide, from the specification"
+//! ```
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::dna::DNA;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Page {
+    /// Names the image of the page, so it can only have letters, digits, `-`
+    /// and `_`.
+    pub name: String,
+    /// The bases of the prefix. Whitespace is ignored.
+    pub prefix: String,
+    pub description: String,
+}
+
+impl Page {
+    pub fn prefix(&self) -> DNA {
+        self.prefix.as_str().into()
+    }
+
+    /// The file the image of the page is written to.
+    pub fn file_name(&self) -> String {
+        format!("{}.png", self.name)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Catalog {
+    pub pages: Vec<Page>,
+}
+
+/// The string `key` of a page, or `default` if the page has no such key.
+fn string(page: &toml::Value, key: &str, default: Option<&str>) -> Result<String, String> {
+    match (page.get(key), default) {
+        (Some(toml::Value::String(s)), _) => Ok(s.clone()),
+        (Some(_), _) => Err(format!("{} of a page is not a string", key)),
+        (None, Some(default)) => Ok(default.to_string()),
+        (None, None) => Err(format!("a page has no {}", key)),
+    }
+}
+
+impl Catalog {
+    pub fn parse(text: &str) -> Result<Catalog, String> {
+        let value: toml::Value = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let pages = match value.get("page") {
+            Some(toml::Value::Array(pages)) => pages.as_slice(),
+            Some(_) => return Err("page is not an array of tables".to_string()),
+            None => &[],
+        };
+        let mut catalog = Catalog { pages: vec![] };
+        for page in pages {
+            catalog.pages.push(Page {
+                name: string(page, "name", None)?,
+                prefix: string(page, "prefix", None)?,
+                description: string(page, "description", Some(""))?,
+            });
+        }
+        let mut names = HashSet::new();
+        for page in &catalog.pages {
+            let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+            if page.name.is_empty() || !page.name.chars().all(valid) {
+                return Err(format!("bad page name {:?}", page.name));
+            }
+            if !names.insert(&page.name) {
+                return Err(format!("page {:?} is in the catalog twice", page.name));
+            }
+            if let Some(c) = page.prefix.chars().find(|c| !"ICFP".contains(*c) && !c.is_whitespace()) {
+                return Err(format!("bad base {:?} in the prefix of {:?}", c, page.name));
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// An HTML page showing the images of all pages, which are expected to be
+    /// in the same directory.
+    pub fn write_index(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "<!DOCTYPE html>")?;
+        writeln!(w, "<html><head><meta charset=\"utf-8\"><title>Endo pages</title></head><body>")?;
+        writeln!(w, "<h1>Endo pages</h1>")?;
+        for page in &self.pages {
+            writeln!(w, "<h2 id=\"{0}\"><a href=\"#{0}\">{0}</a></h2>", page.name)?;
+            if !page.description.is_empty() {
+                writeln!(w, "<p>{}</p>", escape(&page.description))?;
+            }
+            writeln!(w, "<p><code>{}</code></p>", escape(&page.prefix))?;
+            writeln!(w, "<img src=\"{}\" width=\"600\" height=\"600\">", page.file_name())?;
+        }
+        writeln!(w, "</body></html>")
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CATALOG: &str = r#"
+        [[page]]
+        name = "help"
+        prefix = "IIPIFFCPICICIICPIICIPPPICIIC"
+        description = "Repair guide <index>"
+
+        [[page]]
+        name = "empty"
+        prefix = ""
+    "#;
+
+    #[test]
+    fn test_parse() {
+        let catalog = Catalog::parse(CATALOG).unwrap();
+        assert_eq!(catalog.pages.len(), 2);
+        assert_eq!(catalog.pages[0].prefix().len(), 28);
+        assert_eq!(catalog.pages[1].description, "");
+        assert_eq!(Catalog::parse("").unwrap().pages, vec![]);
+
+        let page = |name: &str, prefix: &str| format!("[[page]]\nname = {:?}\nprefix = {:?}\n", name, prefix);
+        assert!(Catalog::parse(&page("../x", "I")).is_err());
+        assert!(Catalog::parse(&page("a", "IX")).is_err());
+        assert!(Catalog::parse(&(page("a", "I") + &page("a", "C"))).is_err());
+        assert!(Catalog::parse("[[page]]\nname = \"a\"\n").is_err());
+        assert!(Catalog::parse("[[page]]\nname = 1\nprefix = \"I\"\n").is_err());
+        assert!(Catalog::parse("page = 1").is_err());
+    }
+
+    #[test]
+    fn test_index() {
+        let mut html = vec![];
+        Catalog::parse(CATALOG).unwrap().write_index(&mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("<img src=\"help.png\""));
+        assert!(html.contains("<img src=\"empty.png\""));
+        assert!(html.contains("Repair guide &lt;index&gt;"));
+    }
+}
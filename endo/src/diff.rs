@@ -0,0 +1,152 @@
+//! Comparison of a rendered image with the target, showing where they differ.
+
+use std::io::{self, Write};
+
+use crate::render::{Bitmap, Pixel, HEIGHT, WIDTH};
+
+/// Side length of the square tiles mismatches are counted in.
+pub const TILE: usize = 20;
+const TILES_X: usize = WIDTH / TILE;
+const TILES_Y: usize = HEIGHT / TILE;
+
+/// Number of tiles listed in the summary.
+const WORST_TILES: usize = 10;
+
+/// The pixels where an image differs from the target. Transparency is ignored,
+/// as in the final image.
+pub struct Diff {
+    mismatched: Vec<bool>,
+    /// Mismatches per tile, row by row.
+    tiles: Vec<usize>,
+}
+
+fn same(a: Pixel, b: Pixel) -> bool {
+    (a.r, a.g, a.b) == (b.r, b.g, b.b)
+}
+
+impl Diff {
+    pub fn new(image: &Bitmap, target: &Bitmap) -> Self {
+        let mismatched: Vec<bool> = image
+            .pixels()
+            .iter()
+            .zip(target.pixels())
+            .map(|(&a, &b)| !same(a, b))
+            .collect();
+        let mut tiles = vec![0; TILES_X * TILES_Y];
+        for (i, _) in mismatched.iter().enumerate().filter(|(_, &m)| m) {
+            let (x, y) = (i % WIDTH, i / WIDTH);
+            tiles[y / TILE * TILES_X + x / TILE] += 1;
+        }
+        Diff { mismatched, tiles }
+    }
+
+    /// Number of pixels that differ.
+    pub fn count(&self) -> usize {
+        self.tiles.iter().sum()
+    }
+
+    /// The contest score of a prefix of length `prefix_len` rendering the
+    /// image, where lower is better.
+    pub fn risk(&self, prefix_len: usize) -> usize {
+        10 * self.count() + prefix_len
+    }
+
+    /// The target with matching pixels dimmed to gray and mismatches in red.
+    pub fn image(&self, target: &Bitmap) -> Bitmap {
+        let mut out = Bitmap::default();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let p = if self.mismatched[y * WIDTH + x] {
+                    Pixel { r: 255, g: 0, b: 0, a: 255 }
+                } else {
+                    let t = target.get(x, y);
+                    let gray = ((t.r as u32 + t.g as u32 + t.b as u32) / 6 + 64) as u8;
+                    Pixel { r: gray, g: gray, b: gray, a: 255 }
+                };
+                out.set(x, y, p);
+            }
+        }
+        out
+    }
+
+    /// Every tile colored by its share of mismatched pixels, from black for
+    /// none through red to yellow for all.
+    pub fn heat_map(&self) -> Bitmap {
+        let mut out = Bitmap::default();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let n = self.tiles[y / TILE * TILES_X + x / TILE];
+                let heat = n * 510 / (TILE * TILE);
+                let p = Pixel {
+                    r: heat.min(255) as u8,
+                    g: heat.saturating_sub(255) as u8,
+                    b: 0,
+                    a: 255,
+                };
+                out.set(x, y, p);
+            }
+        }
+        out
+    }
+
+    /// Writes the total and the tiles with the most mismatches.
+    pub fn write_summary(&self, w: &mut dyn Write) -> io::Result<()> {
+        let count = self.count();
+        writeln!(
+            w,
+            "{} of {} pixels differ ({:.2}%), risk {} before the prefix length",
+            count,
+            WIDTH * HEIGHT,
+            100.0 * count as f64 / (WIDTH * HEIGHT) as f64,
+            10 * count
+        )?;
+        let mut worst: Vec<_> = self.tiles.iter().enumerate().filter(|(_, &n)| n > 0).collect();
+        worst.sort_by_key(|&(i, &n)| (std::cmp::Reverse(n), i));
+        for (i, n) in worst.into_iter().take(WORST_TILES) {
+            let (x, y) = (i % TILES_X * TILE, i / TILES_X * TILE);
+            writeln!(
+                w,
+                "  x {:>3}..{:<3} y {:>3}..{:<3} {:>3} of {}",
+                x,
+                x + TILE,
+                y,
+                y + TILE,
+                n,
+                TILE * TILE
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let target = Bitmap::default();
+        let mut image = Bitmap::default();
+        let white = Pixel { r: 255, g: 255, b: 255, a: 255 };
+        for x in 0..TILE {
+            image.set(x, 0, white);
+        }
+        image.set(599, 599, white);
+        // Transparency alone is no difference
+        image.set(300, 300, Pixel { a: 255, ..Pixel::default() });
+
+        let diff = Diff::new(&image, &target);
+        assert_eq!(diff.count(), TILE + 1);
+        assert_eq!(diff.image(&target).get(599, 599).r, 255);
+        assert_eq!(diff.heat_map().get(TILE - 1, TILE - 1).r, 25);
+        assert_eq!(diff.heat_map().get(TILE, 0).r, 0);
+
+        let mut out = vec![];
+        diff.write_summary(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "21 of 360000 pixels differ (0.01%), risk 210 before the prefix length");
+        assert_eq!(lines[1], "  x   0..20  y   0..20   20 of 400");
+        assert_eq!(lines[2], "  x 580..600 y 580..600   1 of 400");
+    }
+}
@@ -1,9 +1,15 @@
-use im::vector::Vector;
+use im::vector::{Focus, Vector};
 use std::fmt;
 use std::convert::{TryFrom, TryInto};
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::str::FromStr;
 
+/// Bases are ordered as in `ALL`, which is also the order of their values as
+/// `u8`.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub enum Base {
     I,
     C,
@@ -11,6 +17,16 @@ pub enum Base {
     P,
 }
 
+impl Base {
+    pub const ALL: [Base; 4] = [Base::I, Base::C, Base::F, Base::P];
+
+    /// The base at the other end of the order: I and P are each other's
+    /// complement, and so are C and F.
+    pub fn complement(self) -> Base {
+        Base::ALL[3 - self as usize]
+    }
+}
+
 impl TryFrom<char> for Base {
     type Error = ();
     fn try_from(c: char) -> Result<Self, ()> {
@@ -35,7 +51,7 @@ impl From<Base> for char {
     }
 }
 
-#[derive(Default, Clone, PartialEq, Eq)]
+#[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DNA {
     v: Vector<Base>,
 }
@@ -53,7 +69,42 @@ impl From<&str> for DNA {
     }
 }
 
-impl fmt::Debug for DNA {
+/// The error of parsing a `DNA` from a string with a character that is
+/// neither a base nor whitespace.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseDNAError {
+    /// Byte offset of the character.
+    pub offset: usize,
+    pub found: char,
+}
+
+impl fmt::Display for ParseDNAError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at offset {} is not a base", self.found, self.offset)
+    }
+}
+
+impl std::error::Error for ParseDNAError {}
+
+/// Unlike `From<&str>`, which skips anything that is not a base, only
+/// whitespace may come between the bases.
+impl FromStr for DNA {
+    type Err = ParseDNAError;
+
+    fn from_str(s: &str) -> Result<Self, ParseDNAError> {
+        let mut v = Vector::new();
+        for (offset, c) in s.char_indices() {
+            match c.try_into() {
+                Ok(b) => v.push_back(b),
+                Err(()) if c.is_whitespace() => (),
+                Err(()) => return Err(ParseDNAError { offset, found: c }),
+            }
+        }
+        Ok(DNA { v })
+    }
+}
+
+impl fmt::Display for DNA {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for &b in &self.v {
             let c: char = b.into();
@@ -63,17 +114,63 @@ impl fmt::Debug for DNA {
     }
 }
 
+impl fmt::Debug for DNA {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Serialized as a string of bases, as written by `Display`.
+#[cfg(feature = "serialize")]
+impl serde::Serialize for DNA {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'de> serde::Deserialize<'de> for DNA {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'_, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromIterator<Base> for DNA {
+    fn from_iter<T: IntoIterator<Item = Base>>(iter: T) -> Self {
+        DNA { v: iter.into_iter().collect() }
+    }
+}
+
+impl Extend<Base> for DNA {
+    fn extend<T: IntoIterator<Item = Base>>(&mut self, iter: T) {
+        self.v.extend(iter)
+    }
+}
+
+impl Index<usize> for DNA {
+    type Output = Base;
+
+    fn index(&self, index: usize) -> &Base {
+        &self.v[index]
+    }
+}
+
 impl DNA {
     pub fn len(&self) -> usize {
         self.v.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.v.is_empty()
+    }
+
     pub fn pop(&mut self) -> Option<Base> {
         self.v.pop_front()
     }
 
-    pub fn peek(&mut self) -> Option<Base> {
-        self.v.front().map(|b| *b)
+    pub fn peek(&self) -> Option<Base> {
+        self.v.front().copied()
     }
 
     pub fn drop(&mut self, count: usize) {
@@ -101,8 +198,25 @@ impl DNA {
         }
     }
 
+    pub fn starts_with(&self, prefix: &DNA) -> bool {
+        prefix.len() <= self.len() && self.v.iter().zip(prefix.v.iter()).all(|(a, b)| a == b)
+    }
+
+    pub fn iter(&self) -> im::vector::Iter<'_, Base> {
+        self.v.iter()
+    }
+
+    /// A cursor reading from `pos` onwards.
+    pub fn cursor(&self, pos: usize) -> Cursor<'_> {
+        Cursor {
+            dna: self,
+            focus: self.v.focus(),
+            pos: pos.min(self.len()),
+        }
+    }
+
     pub fn at(&self, index: usize) -> Option<Base> {
-        self.v.get(index).map(|b| *b)
+        self.v.get(index).copied()
     }
 
     pub fn find_first(&self, needle: &DNA,  from: usize) -> Option<usize> {
@@ -110,10 +224,10 @@ impl DNA {
         // of `self[from,...]`.
         let len = needle.len();
         if len == 0 {
-            return Some(0);
+            return if from <= self.len() { Some(from) } else { None };
         }
         let mut i = from;
-        while i <= self.len() - len {
+        while i + len <= self.len() {
             let mut j = 0;
             while self.at(i+j) == needle.at(j) {
                 j += 1;
@@ -149,11 +263,60 @@ impl DNA {
         None
     }
 
+    /// Replaces the first `len` bases by `prefix`. The rest of the DNA is not
+    /// copied, so this is cheap even when it is long.
+    pub fn splice(&mut self, len: usize, mut prefix: DNA) {
+        let tail = self.v.split_off(len.min(self.v.len()));
+        prefix.v.append(tail);
+        self.v = prefix.v;
+    }
+
     pub fn assign(&mut self,  other: DNA) {
         self.v = other.v
     }
 }
 
+/// Reads a `DNA` from left to right without modifying it.
+pub struct Cursor<'a> {
+    dna: &'a DNA,
+    focus: Focus<'a, Base>,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// Number of bases read from the start of the `DNA`.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// The base `offset` bases ahead, without consuming anything.
+    pub fn peek(&mut self, offset: usize) -> Option<Base> {
+        self.focus.get(self.pos + offset).copied()
+    }
+
+    /// Consumes `count` bases, or fewer at the end.
+    pub fn advance(&mut self, count: usize) {
+        self.pos = self.pos.saturating_add(count).min(self.dna.len());
+    }
+
+    /// Consumes and returns the next `count` bases, or fewer at the end.
+    pub fn read(&mut self, count: usize) -> DNA {
+        let d = self.dna.subseq(self.pos, self.pos.saturating_add(count));
+        self.advance(count);
+        d
+    }
+}
+
+impl<'a> Iterator for Cursor<'a> {
+    type Item = Base;
+
+    fn next(&mut self) -> Option<Base> {
+        let b = self.peek(0)?;
+        self.pos += 1;
+        Some(b)
+    }
+}
+
 impl IntoIterator for DNA {
     type Item = Base;
     type IntoIter = im::vector::ConsumingIter<Self::Item>;
@@ -163,11 +326,21 @@ impl IntoIterator for DNA {
     }
 }
 
+impl<'a> IntoIterator for &'a DNA {
+    type Item = &'a Base;
+    type IntoIter = im::vector::Iter<'a, Base>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.v.iter()
+    }
+}
+
 
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_drop() {
@@ -191,6 +364,35 @@ mod test {
         assert_eq!(dna.subseq(3, 0), "".into());
     }
 
+    #[test]
+    fn test_splice() {
+        let mut dna: DNA = "ICFP".into();
+        dna.splice(2, "PPP".into());
+        assert_eq!(dna, "PPPFP".into());
+        dna.splice(0, "I".into());
+        assert_eq!(dna, "IPPPFP".into());
+        dna.splice(5, "".into());
+        assert_eq!(dna, "P".into());
+        dna.splice(3, "CC".into());
+        assert_eq!(dna, "CC".into());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let dna: DNA = "ICFPICFP".into();
+        let mut c = dna.cursor(1);
+        assert_eq!(c.peek(0), Some(Base::C));
+        assert_eq!(c.peek(6), Some(Base::P));
+        assert_eq!(c.peek(7), None);
+        assert_eq!(c.next(), Some(Base::C));
+        assert_eq!(c.read(3), "FPI".into());
+        assert_eq!(c.pos(), 5);
+        assert_eq!(c.read(5), "CFP".into());
+        assert_eq!(c.pos(), 8);
+        assert_eq!(c.next(), None);
+        assert_eq!(dna, "ICFPICFP".into());
+    }
+
     #[test]
     fn test_find_first() {
         let mut dna: DNA = "I IC ICF ICF".into();
@@ -214,4 +416,77 @@ mod test {
         assert_eq!(dna.find_first(&"F".into(), 9), None);
         assert_eq!(dna.find_first(&"F".into(), 10), None);
     }
+
+    #[test]
+    fn test_base() {
+        assert!(Base::ALL.windows(2).all(|w| w[0] < w[1]));
+        for (i, &b) in Base::ALL.iter().enumerate() {
+            assert_eq!(b as usize, i);
+            assert_eq!(b.complement().complement(), b);
+            assert_ne!(b.complement(), b);
+        }
+        assert_eq!(Base::C.complement(), Base::F);
+    }
+
+    #[test]
+    fn test_traits() {
+        let dna: DNA = "IC FP\n".parse().unwrap();
+        assert_eq!(dna.to_string(), "ICFP");
+        assert_eq!(format!("{:?}", dna), "ICFP");
+        assert_eq!("ICXP".parse::<DNA>(), Err(ParseDNAError { offset: 2, found: 'X' }));
+
+        assert_eq!(dna[2], Base::F);
+        assert_eq!(dna.peek(), Some(Base::I));
+        assert_eq!(dna.iter().rev().copied().collect::<DNA>(), "PFCI".into());
+        let mut d: DNA = (&dna).into_iter().map(|b| b.complement()).collect();
+        d.extend(vec![Base::I]);
+        assert_eq!(d, "PFCII".into());
+        assert!(DNA::from("ICF") < DNA::from("ICP"));
+
+        let set: std::collections::HashSet<DNA> = vec![dna.clone(), "ICFP".into(), d].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn test_serde() {
+        let dna: DNA = "ICFP".into();
+        assert_eq!(serde_json::to_string(&dna).unwrap(), "\"ICFP\"");
+        assert_eq!(serde_json::from_str::<DNA>("\"IC FP\"").unwrap(), dna);
+        assert!(serde_json::from_str::<DNA>("\"ICXP\"").is_err());
+        assert_eq!(serde_json::to_string(&Base::F).unwrap(), "\"F\"");
+    }
+
+    /// The search specified by the pattern item `?s`, on strings.
+    fn find_first_reference(haystack: &str, needle: &str, from: usize) -> Option<usize> {
+        (from..=haystack.len()).find(|&n| haystack[from..n].ends_with(needle))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_subseq_concat(s in "[ICFP]{0,30}", i in 0usize..35, j in 0usize..35, k in 0usize..35) {
+            let dna: DNA = s.as_str().into();
+            let mut ij = dna.subseq(i, j);
+            ij.concat(dna.subseq(j.max(i), k));
+            prop_assert_eq!(ij, dna.subseq(i, k.max(j)));
+        }
+
+        #[test]
+        fn prop_concat_subseq(a in "[ICFP]{0,30}", b in "[ICFP]{0,30}") {
+            let (a, b): (DNA, DNA) = (a.as_str().into(), b.as_str().into());
+            let mut ab = a.clone();
+            ab.concat(b.clone());
+            prop_assert_eq!(ab.len(), a.len() + b.len());
+            prop_assert_eq!(ab.subseq(0, a.len()), a.clone());
+            prop_assert_eq!(ab.subseq(a.len(), ab.len()), b);
+        }
+
+        #[test]
+        fn prop_find_first(haystack in "[ICFP]{0,30}", needle in "[ICFP]{0,3}", from in 0usize..35) {
+            let expected = find_first_reference(&haystack, &needle, from);
+            let (h, n): (DNA, DNA) = (haystack.as_str().into(), needle.as_str().into());
+            prop_assert_eq!(h.find_first(&n, from), expected);
+            prop_assert_eq!(h.find_first_jonas(&n, from), expected);
+        }
+    }
 }
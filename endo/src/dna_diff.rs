@@ -0,0 +1,219 @@
+//! Differences between two DNA sequences of millions of bases, such as the DNA
+//! before and after running a prefix. After the common prefix and suffix are
+//! trimmed, every `K`th `K`-mer of the old DNA is indexed and the new DNA is
+//! scanned with a rolling `K`-mer for anchors: runs of bases found in both.
+//! The longest chain of anchors in the same order in both is kept, and what
+//! lies between them are the edits.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::dna::{Base, DNA};
+
+/// Length of the runs of bases used as anchors. With two bits per base, a
+/// run is its own hash, so there are no false anchors. Any common run of at
+/// least `2 * K - 1` bases contains an indexed one.
+const K: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Inserted,
+    Deleted,
+    Replaced,
+}
+
+/// Bases `old` of the old DNA were replaced by bases `new` of the new DNA.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Edit {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+impl Edit {
+    pub fn kind(&self) -> Kind {
+        match (self.old.is_empty(), self.new.is_empty()) {
+            (true, _) => Kind::Inserted,
+            (false, true) => Kind::Deleted,
+            (false, false) => Kind::Replaced,
+        }
+    }
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (old, new) = (&self.old, &self.new);
+        match self.kind() {
+            Kind::Inserted => write!(f, "inserted new {:?} ({} bases) at old {}", new, new.len(), old.start),
+            Kind::Deleted => write!(f, "deleted old {:?} ({} bases)", old, old.len()),
+            Kind::Replaced => {
+                write!(f, "replaced old {:?} ({} bases) with new {:?} ({} bases)", old, old.len(), new, new.len())
+            }
+        }
+    }
+}
+
+/// A run of `len` bases at `old` in the old DNA and `new` in the new.
+#[derive(Clone, Copy, Debug)]
+struct Anchor {
+    old: usize,
+    new: usize,
+    len: usize,
+}
+
+/// The edits turning `old` into `new`, in order.
+pub fn diff(old: &DNA, new: &DNA) -> Vec<Edit> {
+    let a: Vec<Base> = old.iter().copied().collect();
+    let b: Vec<Base> = new.iter().copied().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut edits = vec![];
+    let (mut i, mut j) = (0, 0);
+    for mut anchor in chain(anchors(a, b)) {
+        // Anchors in the chain can overlap in the old DNA after extending
+        if anchor.old < i {
+            let overlap = i - anchor.old;
+            if overlap >= anchor.len {
+                continue;
+            }
+            anchor = Anchor { old: i, new: anchor.new + overlap, len: anchor.len - overlap };
+        }
+        if anchor.old > i || anchor.new > j {
+            edits.push(Edit { old: i..anchor.old, new: j..anchor.new });
+        }
+        i = anchor.old + anchor.len;
+        j = anchor.new + anchor.len;
+    }
+    if i < a.len() || j < b.len() {
+        edits.push(Edit { old: i..a.len(), new: j..b.len() });
+    }
+    for e in &mut edits {
+        e.old = e.old.start + prefix..e.old.end + prefix;
+        e.new = e.new.start + prefix..e.new.end + prefix;
+    }
+    edits
+}
+
+/// Maximal common runs of `a` and `b` through an indexed `K`-mer of `a`, in
+/// order of and not overlapping in `b`.
+fn anchors(a: &[Base], b: &[Base]) -> Vec<Anchor> {
+    // `None` for K-mers found more than once, which make poor anchors
+    let mut index: HashMap<u64, Option<usize>> = HashMap::new();
+    for p in (0..(a.len() + 1).saturating_sub(K)).step_by(K) {
+        let key = a[p..p + K].iter().fold(0, |key, &base| key << 2 | base as u64);
+        index.entry(key).and_modify(|p| *p = None).or_insert(Some(p));
+    }
+
+    let mut anchors: Vec<Anchor> = vec![];
+    let mut key = 0u64;
+    let mut covered = 0;
+    for (end, &base) in b.iter().enumerate().map(|(j, base)| (j + 1, base)) {
+        key = key << 2 | base as u64;
+        if end < K || end - K < covered {
+            continue;
+        }
+        let (p, start) = match index.get(&key) {
+            Some(&Some(p)) => (p, end - K),
+            _ => continue,
+        };
+        let back = (1..=p.min(start - covered)).take_while(|&n| a[p - n] == b[start - n]).count();
+        let forward = (K..).take_while(|&n| p + n < a.len() && start + n < b.len() && a[p + n] == b[start + n]);
+        let len = forward.count() + K;
+        anchors.push(Anchor { old: p - back, new: start - back, len: len + back });
+        covered = start + len;
+    }
+    anchors
+}
+
+/// The longest subsequence of `anchors` in increasing order in the old DNA.
+fn chain(anchors: Vec<Anchor>) -> Vec<Anchor> {
+    // Patience sorting: `tails[n]` ends the chain of length `n + 1` ending
+    // earliest in the old DNA
+    let mut tails: Vec<usize> = vec![];
+    let mut previous = vec![None; anchors.len()];
+    for (n, anchor) in anchors.iter().enumerate() {
+        let at = tails.partition_point(|&t| anchors[t].old < anchor.old);
+        if at > 0 {
+            previous[n] = Some(tails[at - 1]);
+        }
+        if at == tails.len() {
+            tails.push(n);
+        } else {
+            tails[at] = n;
+        }
+    }
+    let mut chain = vec![];
+    let mut n = tails.last().copied();
+    while let Some(i) = n {
+        chain.push(anchors[i]);
+        n = previous[i];
+    }
+    chain.reverse();
+    chain
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random(rng: &mut StdRng, len: usize) -> Vec<Base> {
+        (0..len).map(|_| Base::ALL[rng.gen_range(0..4)]).collect()
+    }
+
+    fn dna(bases: &[Base]) -> DNA {
+        bases.iter().copied().collect()
+    }
+
+    /// Checks that the bases between the edits are the same in both.
+    fn check(a: &[Base], b: &[Base], edits: &[Edit]) {
+        let (mut i, mut j) = (0, 0);
+        for e in edits {
+            assert_eq!(e.old.start - i, e.new.start - j);
+            assert_eq!(a[i..e.old.start], b[j..e.new.start]);
+            i = e.old.end;
+            j = e.new.end;
+        }
+        assert_eq!(a[i..], b[j..]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let a = random(&mut rng, 10_000);
+        let mut b = a[..1000].to_vec();
+        b.extend(&a[1500..5000]);
+        b.extend(random(&mut rng, 77));
+        b.extend(&a[5000..8000]);
+        // Every base changed, so the replacement can't shrink
+        b.extend(a[8000..8100].iter().map(|&x| Base::ALL[(x as usize + 1) % 4]));
+        b.extend(&a[8100..]);
+
+        let edits = diff(&dna(&a), &dna(&b));
+        check(&a, &b, &edits);
+        let kinds: Vec<_> = edits.iter().map(|e| (e.kind(), e.old.len(), e.new.len())).collect();
+        assert_eq!(kinds, [(Kind::Deleted, 500, 0), (Kind::Inserted, 0, 77), (Kind::Replaced, 100, 100)]);
+        assert_eq!(edits[2], Edit { old: 8000..8100, new: 7577..7677 });
+        assert_eq!(edits[2].to_string(), "replaced old 8000..8100 (100 bases) with new 7577..7677 (100 bases)");
+
+        // A moved block is a deletion and an insertion
+        let mut moved = a[2000..].to_vec();
+        moved.extend(&a[..2000]);
+        let edits = diff(&dna(&a), &dna(&moved));
+        check(&a, &moved, &edits);
+        assert_eq!(edits.len(), 2);
+    }
+
+    #[test]
+    fn test_diff_short() {
+        let d = |a: &str, b: &str| diff(&a.into(), &b.into());
+        assert_eq!(d("ICFP", "ICFP"), vec![]);
+        assert_eq!(d("ICFP", "ICCP"), vec![Edit { old: 2..3, new: 2..3 }]);
+        assert_eq!(d("ICFP", "IP"), vec![Edit { old: 1..3, new: 1..1 }]);
+        assert_eq!(d("", "CC"), vec![Edit { old: 0..0, new: 0..2 }]);
+        assert_eq!(d("II", "IIII")[0].kind(), Kind::Inserted);
+    }
+}
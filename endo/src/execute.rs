@@ -1,22 +1,94 @@
-use crate::dna::{Base, DNA};
+use std::cell::RefCell;
+use std::rc::Rc;
 
-/// The `dna` value is consumed since the implementation does not follow the
-/// specification about mutation of `dna` in the step where the program ends.
-pub fn execute(mut dna: DNA, mut rna_sink: impl FnMut(DNA)) {
-    // TODO: these lines are just for testing. Remove soon.
-    rna_sink("ICFPICFPI".into());
-    rna_sink("CFPICFPIC".into());
+use crate::cache::{Cache, Entry};
+use crate::backend::{Backend, DnaCursor};
+use crate::dna::{Base, Cursor, DNA};
 
-    while step(&mut dna, &mut rna_sink).is_ok() {
+/// Runs `dna` until it finishes, passing every RNA command to `rna_sink`.
+pub fn execute(dna: DNA, rna_sink: impl FnMut(DNA)) {
+    execute_monitored(dna, rna_sink, &mut ())
+}
+
+/// Like `execute`, but reports the progress of every step to `monitor`.
+pub fn execute_monitored(mut dna: DNA, mut rna_sink: impl FnMut(DNA), monitor: &mut dyn Monitor) {
+    while step(&mut dna, &mut rna_sink, monitor).is_ok() {
+        // do nothing
+    }
+}
+
+/// Like `execute_monitored`, but looks up decoded code in `cache` first.
+pub fn execute_cached(
+    mut dna: DNA,
+    mut rna_sink: impl FnMut(DNA),
+    monitor: &mut dyn Monitor,
+    cache: &mut Cache,
+) {
+    while step_cached(&mut dna, &mut rna_sink, monitor, cache).is_ok() {
         // do nothing
     }
 }
 
+/// Observer of the phases of each step, used for profiling. Every method
+/// defaults to doing nothing.
+pub trait Monitor {
+    /// A step is about to decode its pattern from the front of `dna`.
+    fn begin_step(&mut self, _dna: &DNA) {}
+
+    /// Pattern and template were decoded, consuming `len` bases in total.
+    fn decoded(&mut self, _pattern: &[PItem], _template: &[TItem], _len: usize) {}
+
+    /// The pattern was matched against `dna`, which no longer contains the
+    /// decoded pattern and template. `None` means the match failed.
+    fn matched(&mut self, _dna: &DNA, _m: Option<&Match>) {}
+
+    /// The matched prefix was replaced by `len` bases of output from
+    /// `template`.
+    fn replaced(&mut self, _template: &[TItem], _len: usize) {}
+
+    /// The step finished, leaving `dna` behind.
+    fn end_step(&mut self, _dna: &DNA) {}
+
+    /// `rna` was emitted while decoding.
+    fn rna(&mut self, _rna: &DNA) {}
+}
+
+impl Monitor for () {}
+
+/// Forwards to every monitor in turn.
+impl Monitor for Vec<&mut dyn Monitor> {
+    fn begin_step(&mut self, dna: &DNA) {
+        self.iter_mut().for_each(|m| m.begin_step(dna))
+    }
+
+    fn decoded(&mut self, pattern: &[PItem], template: &[TItem], len: usize) {
+        self.iter_mut().for_each(|m| m.decoded(pattern, template, len))
+    }
+
+    fn matched(&mut self, dna: &DNA, m: Option<&Match>) {
+        self.iter_mut().for_each(|monitor| monitor.matched(dna, m))
+    }
+
+    fn replaced(&mut self, template: &[TItem], len: usize) {
+        self.iter_mut().for_each(|m| m.replaced(template, len))
+    }
+
+    fn end_step(&mut self, dna: &DNA) {
+        self.iter_mut().for_each(|m| m.end_step(dna))
+    }
+
+    fn rna(&mut self, rna: &DNA) {
+        self.iter_mut().for_each(|m| m.rna(rna))
+    }
+}
+
+/// Signals that the DNA ran out, which ends execution.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
-struct Finish;
+pub struct Finish;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
-enum PItem {
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum PItem {
     Base(Base),
     Skip(usize),
     Search(DNA),
@@ -27,7 +99,8 @@ enum PItem {
 type Pattern = Vec<PItem>;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
-enum TItem {
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum TItem {
     Base(Base),
     Ref { n: usize, l: usize },
     RefLen(usize),
@@ -35,50 +108,135 @@ enum TItem {
 
 type Template = Vec<TItem>;
 
-fn step(dna: &mut DNA, rna_sink: &mut dyn FnMut(DNA)) -> Result<(), Finish> {
-    let p = pattern(dna, rna_sink)?;
-    let t = template(dna, rna_sink)?;
-    matchreplace(dna, p, t);
+/// A successful match of a pattern.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Match {
+    /// Number of bases matched, all of which are replaced.
+    pub len: usize,
+    /// The `[start, end)` span of each group, in the order they were closed.
+    pub env: Vec<(usize, usize)>,
+}
+
+/// Runs one iteration: decode a pattern and a template and apply them. The
+/// DNA is only modified once both are decoded, so it is left unchanged in the
+/// step where execution finishes.
+pub fn step(dna: &mut DNA, rna_sink: &mut dyn FnMut(DNA), monitor: &mut dyn Monitor) -> Result<(), Finish> {
+    monitor.begin_step(dna);
+    let mut c = dna.cursor(0);
+    let mut sink = |r: DNA| {
+        monitor.rna(&r);
+        rna_sink(r)
+    };
+    let p = pattern(&mut c, &mut sink)?;
+    let t = template(&mut c, &mut sink)?;
+    let len = c.pos();
+    monitor.decoded(&p, &t, len);
+    dna.drop(len);
+    matchreplace(dna, &p, &t, monitor);
+    monitor.end_step(dna);
     Ok(())
 }
 
-/// May leave `dna` inconsistent when EOF reached
-fn pattern(mut dna: &mut DNA, rna_sink: &mut dyn FnMut(DNA)) -> Result<Pattern, Finish> {
+/// Like `step`, but looks up the code at the front of `dna` in `cache`, and
+/// adds it there when missing.
+pub fn step_cached(
+    dna: &mut DNA,
+    rna_sink: &mut dyn FnMut(DNA),
+    monitor: &mut dyn Monitor,
+    cache: &mut Cache,
+) -> Result<(), Finish> {
+    monitor.begin_step(dna);
+    let entry = match cache.get(dna) {
+        Some(entry) => {
+            for r in &entry.rna {
+                monitor.rna(r);
+                rna_sink(r.clone());
+            }
+            entry
+        }
+        None => {
+            let mut c = dna.cursor(0);
+            let mut rna = vec![];
+            let mut sink = |r: DNA| {
+                monitor.rna(&r);
+                rna.push(r.clone());
+                rna_sink(r)
+            };
+            let pattern = pattern(&mut c, &mut sink)?;
+            let template = template(&mut c, &mut sink)?;
+            let code = dna.subseq(0, c.pos());
+            let entry = Rc::new(Entry { code, pattern, template, rna });
+            cache.insert(dna, entry.clone());
+            entry
+        }
+    };
+    monitor.decoded(&entry.pattern, &entry.template, entry.code.len());
+    dna.drop(entry.code.len());
+    matchreplace(dna, &entry.pattern, &entry.template, monitor);
+    monitor.end_step(dna);
+    Ok(())
+}
+
+/// Like `step`, but for any `Backend`. Both the pattern and the template are
+/// decoded before matching, and nothing is monitored.
+pub fn step_backend<B: Backend>(dna: &mut B, rna_sink: &mut dyn FnMut(DNA)) -> Result<(), Finish> {
+    let mut c = dna.cursor(0);
+    let p = pattern(&mut c, rna_sink)?;
+    let t = template(&mut c, rna_sink)?;
+    let len = c.pos();
+    drop(c);
+    dna.drop(len);
+    if let Some(m) = find_match(dna, &p) {
+        let mut r = dna.empty();
+        replace(&t, dna, &m.env, &mut r);
+        dna.splice(m.len, r);
+    }
+    Ok(())
+}
+
+fn pattern(c: &mut impl DnaCursor, rna_sink: &mut dyn FnMut(DNA)) -> Result<Pattern, Finish> {
     let mut p = vec![]; // TODO: avoid allocation?
+    pattern_items(c, rna_sink, &mut |item| p.push(item))?;
+    Ok(p)
+}
+
+/// Decodes a pattern, passing each item to `item` instead of collecting them.
+pub(crate) fn pattern_items(
+    c: &mut impl DnaCursor,
+    rna_sink: &mut dyn FnMut(DNA),
+    item: &mut dyn FnMut(PItem),
+) -> Result<(), Finish> {
     let mut lvl: usize = 0;
     loop {
-        match dna.pop() {
-            Some(Base::C) => p.push(PItem::Base(Base::I)),
-            Some(Base::F) => p.push(PItem::Base(Base::C)),
-            Some(Base::P) => p.push(PItem::Base(Base::F)),
-            Some(Base::I) => match dna.pop() {
-                Some(Base::C) => p.push(PItem::Base(Base::P)),
+        match c.next() {
+            Some(Base::C) => item(PItem::Base(Base::I)),
+            Some(Base::F) => item(PItem::Base(Base::C)),
+            Some(Base::P) => item(PItem::Base(Base::F)),
+            Some(Base::I) => match c.next() {
+                Some(Base::C) => item(PItem::Base(Base::P)),
                 Some(Base::P) => {
-                    let n = nat(&mut dna)?;
-                    p.push(PItem::Skip(n));
+                    let n = nat(c)?;
+                    item(PItem::Skip(n));
                 }
                 Some(Base::F) => {
-                    dna.pop(); // quirk of the specification
-                    let s = consts(&mut dna);
-                    p.push(PItem::Search(s));
+                    c.next(); // quirk of the specification
+                    let s = consts(c);
+                    item(PItem::Search(s));
                 }
-                Some(Base::I) => match dna.pop() {
+                Some(Base::I) => match c.next() {
                     Some(Base::P) => {
                         lvl += 1;
-                        p.push(PItem::Open())
+                        item(PItem::Open())
                     }
                     Some(Base::C) | Some(Base::F) => {
                         if lvl == 0 {
-                            return Ok(p);
+                            return Ok(());
                         } else {
-                            lvl = lvl - 1;
-                            p.push(PItem::Close());
+                            lvl -= 1;
+                            item(PItem::Close());
                         }
                     }
-                    Some(Base::I) => {
-                        rna_sink(dna.subseq(0, 7));
-                        dna.drop(7);
-                    }
+                    Some(Base::I) => rna_sink(c.read(7)),
                     None => break,
                 },
                 None => break,
@@ -89,72 +247,69 @@ fn pattern(mut dna: &mut DNA, rna_sink: &mut dyn FnMut(DNA)) -> Result<Pattern,
     Err(Finish)
 }
 
-/// MSB is last
-fn nat(dna: &mut DNA) -> Result<usize, Finish> {
-    let mut shiftcount = 0;
-    let mut acc = 0;
-    while let Some(b) = dna.pop() {
+/// MSB is last. Numbers too large for a `usize` saturate to `usize::MAX`,
+/// which behaves as the real number would: no skip or reference that large
+/// can succeed since no DNA or environment is that long.
+fn nat(c: &mut impl DnaCursor) -> Result<usize, Finish> {
+    let mut shiftcount: u32 = 0;
+    let mut acc: usize = 0;
+    for b in c {
         match b {
             Base::P => return Ok(acc),
             Base::I | Base::F => (), // `|=` with 0 is a no-op
-            Base::C => acc |= (1 << shiftcount),
+            Base::C => acc |= 1usize.checked_shl(shiftcount).unwrap_or(usize::MAX),
         }
-        shiftcount += 1;
+        shiftcount = shiftcount.saturating_add(1);
     }
     Err(Finish)
 }
 
-fn consts(dna: &mut DNA) -> DNA {
+fn consts(c: &mut impl DnaCursor) -> DNA {
     let mut acc = DNA::default();
-    while let Some(b) = dna.pop() {
-        match b {
-            Base::C => acc.append(Base::I),
-            Base::F => acc.append(Base::C),
-            Base::P => acc.append(Base::F),
-            Base::I => match dna.pop() {
-                Some(Base::C) => acc.append(Base::P),
-                Some(b) => {
-                    // Went too far by two
-                    dna.prepend(b);
-                    dna.prepend(Base::I);
-                    return acc;
-                }
-                None => {
-                    // Went too far by one
-                    dna.prepend(Base::I);
-                    return acc;
-                }
-            },
-        }
+    loop {
+        let (b, width) = match (c.peek(0), c.peek(1)) {
+            (Some(Base::C), _) => (Base::I, 1),
+            (Some(Base::F), _) => (Base::C, 1),
+            (Some(Base::P), _) => (Base::F, 1),
+            (Some(Base::I), Some(Base::C)) => (Base::P, 2),
+            _ => return acc,
+        };
+        c.advance(width);
+        acc.append(b);
     }
-    acc
 }
 
-/// May leave `dna` inconsistent when EOF reached
-fn template(mut dna: &mut DNA, rna_sink: &mut dyn FnMut(DNA)) -> Result<Template, Finish> {
-    let mut t = vec![]; // TODO: avoid allocation?
+fn template(c: &mut impl DnaCursor, rna_sink: &mut dyn FnMut(DNA)) -> Result<Template, Finish> {
+    let mut t = vec![];
+    template_items(c, rna_sink, &mut |item| t.push(item))?;
+    Ok(t)
+}
+
+/// Decodes a template, passing each item to `item` instead of collecting them.
+pub(crate) fn template_items(
+    c: &mut impl DnaCursor,
+    rna_sink: &mut dyn FnMut(DNA),
+    item: &mut dyn FnMut(TItem),
+) -> Result<(), Finish> {
     loop {
-        match dna.pop() {
-            Some(Base::C) => t.push(TItem::Base(Base::I)),
-            Some(Base::F) => t.push(TItem::Base(Base::C)),
-            Some(Base::P) => t.push(TItem::Base(Base::F)),
-            Some(Base::I) => match dna.pop() {
-                Some(Base::C) => t.push(TItem::Base(Base::P)),
+        match c.next() {
+            Some(Base::C) => item(TItem::Base(Base::I)),
+            Some(Base::F) => item(TItem::Base(Base::C)),
+            Some(Base::P) => item(TItem::Base(Base::F)),
+            Some(Base::I) => match c.next() {
+                Some(Base::C) => item(TItem::Base(Base::P)),
                 Some(Base::F) | Some(Base::P) => {
-                    let l = nat(&mut dna)?;
-                    let n = nat(&mut dna)?;
-                    t.push(TItem::Ref { n, l });
+                    let l = nat(c)?;
+                    let n = nat(c)?;
+                    item(TItem::Ref { n, l });
                 }
-                Some(Base::I) => match dna.pop() {
-                    Some(Base::C) | Some(Base::F) => return Ok(t),
+                Some(Base::I) => match c.next() {
+                    Some(Base::C) | Some(Base::F) => return Ok(()),
                     Some(Base::P) => {
-                        let n = nat(&mut dna)?;
-                        t.push(TItem::RefLen(n));
-                    }
-                    Some(Base::I) => {
-                        rna_sink(dna.subseq(0, 7));
-                        dna.drop(7);
+                        let n = nat(c)?;
+                        item(TItem::RefLen(n));
                     }
+                    Some(Base::I) => rna_sink(c.read(7)),
                     None => break,
                 },
                 None => break,
@@ -165,28 +320,41 @@ fn template(mut dna: &mut DNA, rna_sink: &mut dyn FnMut(DNA)) -> Result<Template
     Err(Finish)
 }
 
-fn matchreplace(mut dna: &mut DNA, pattern: Pattern, template: Template) {
+/// Matches `pattern` against the front of `dna` and replaces the match by
+/// `template`.
+pub fn matchreplace(dna: &mut DNA, pattern: &[PItem], template: &[TItem], monitor: &mut dyn Monitor) {
+    let m = find_match(dna, pattern);
+    monitor.matched(dna, m.as_ref());
+    if let Some(m) = m {
+        let mut r = DNA::default();
+        replace(template, dna, &m.env, &mut r);
+        monitor.replaced(template, r.len());
+        dna.splice(m.len, r);
+    }
+}
+
+fn find_match<B: Backend>(dna: &B, pattern: &[PItem]) -> Option<Match> {
     let mut i : usize = 0;
-    let mut env : Vec<DNA> = vec![];
+    let mut env : Vec<(usize, usize)> = vec![];
     let mut c_rev : Vec<usize> = vec![];
     for p in pattern {
         match p {
             PItem::Base(b) => {
-                if dna.at(i) == Some(b) {
+                if dna.at(i) == Some(*b) {
                     i += 1
                 } else {
-                    return
+                    return None
                 }
             },
             PItem::Skip(n) => {
-                i += n;
+                i = i.saturating_add(*n);
                 if i > dna.len() {
-                    return
+                    return None
                 }
             },
             PItem::Search(s) => {
-                match dna.find_first(&s, i) {
-                    None => return,
+                match dna.find_first(s, i) {
+                    None => return None,
                     Some(idx) => i = idx,
                 }
             },
@@ -194,42 +362,93 @@ fn matchreplace(mut dna: &mut DNA, pattern: Pattern, template: Template) {
                 c_rev.push(i)
             },
             PItem::Close() => {
-                let from =  c_rev.pop().expect("Closing an unopened group");
-                env.push(dna.subseq(from, i))
+                // An unbalanced pattern never matches
+                let from = c_rev.pop()?;
+                env.push((from, i))
             }
         }
     }
-    let mut r = replace(template, env);
-    let tail = dna.subseq(i, dna.len());
-    dna.assign(r);
-    dna.concat(tail)
+    Some(Match { len: i, env })
 }
 
-fn replace(template: Template, env : Vec<DNA>) -> DNA {
-    let mut r = DNA::default();
+/// Appends the output of `template` to `r`. The environment is given as the
+/// spans of `dna` matched by each group.
+fn replace<B: Backend>(template: &[TItem], dna: &B, env: &[(usize, usize)], r: &mut B) {
     for t in template {
-        match t {
+        match *t {
             TItem::Base(b) => r.append(b),
+            // A reference beyond the environment is to the empty DNA
             TItem::Ref{n, l} => {
-                r.concat(protect(l, env[n].clone()))
+                if let Some(&(from, to)) = env.get(n) {
+                    if l == 0 {
+                        r.concat(dna.subseq(from, to))
+                    } else {
+                        let mut q = DNA::default();
+                        protect_into(l, dna.subseq(from, to).to_dna(), &mut q);
+                        r.append_dna(&q)
+                    }
+                }
             },
             TItem::RefLen(n) => {
-                r.concat(asnat(env[n].len()))
+                let mut q = DNA::default();
+                asnat_into(env.get(n).map_or(0, |&(from, to)| to - from), &mut q);
+                r.append_dna(&q)
             }
         }
     }
+}
+
+/// Deepest level whose expansions are memoized. Expansions grow by about a
+/// fifth with each level, so the table for this level is about a thousand
+/// bases per base, while memoizing a level of a few hundred would keep
+/// gigabytes for the life of the thread.
+const MAX_QUOTED_LEVEL: usize = 32;
+
+thread_local! {
+    /// `QUOTED[l][b]` is the base `b` quoted `l` times, for the levels needed
+    /// so far up to `MAX_QUOTED_LEVEL`. Indexes of `b` are in the order I, C,
+    /// F, P.
+    static QUOTED: RefCell<Vec<[Vec<Base>; 4]>> =
+        RefCell::new(vec![[vec![Base::I], vec![Base::C], vec![Base::F], vec![Base::P]]]);
+}
+
+/// `d` quoted `l` times.
+pub fn protect(l: usize, d: DNA) -> DNA {
+    let mut r = DNA::default();
+    protect_into(l, d, &mut r);
     r
 }
 
-fn protect(l: usize, d: DNA) -> DNA {
-    if l == 0 {
-        d
-    } else {
-        protect(l-1, quote(d))
+/// Appends `d` quoted `l` times to `r`. Each base is replaced by its memoized
+/// expansion, so the cost is proportional to the length of the result. Deeper
+/// levels first quote `d` the levels beyond `MAX_QUOTED_LEVEL`, which costs
+/// the length of that intermediate result as well.
+fn protect_into(l: usize, d: DNA, r: &mut DNA) {
+    if l == 0 || d.is_empty() {
+        return r.concat(d);
+    }
+    if l > MAX_QUOTED_LEVEL {
+        let inner = protect(l - MAX_QUOTED_LEVEL, d);
+        return protect_into(MAX_QUOTED_LEVEL, inner, r);
     }
+    QUOTED.with(|quoted| {
+        let mut quoted = quoted.borrow_mut();
+        while quoted.len() <= l {
+            // Quoting `l` times is quoting `quote(b)` `l-1` times
+            let [i, c, f, p] = quoted.last().unwrap();
+            let next = [c.clone(), f.clone(), p.clone(), [&i[..], &c[..]].concat()];
+            quoted.push(next);
+        }
+        let expansion = &quoted[l];
+        for b in d {
+            for &e in &expansion[b as usize] {
+                r.append(e);
+            }
+        }
+    })
 }
 
-fn quote(d: DNA) -> DNA {
+pub fn quote(d: DNA) -> DNA {
     let mut r = DNA::default();
     for b in d {
         match b {
@@ -282,11 +501,10 @@ mod jonas_matchreplace {
                 }
             }
         }
-        dna.drop(i);
-        replace(dna, t, e);
+        dna.splice(i, replace(t, e));
     }
 
-    fn replace(mut dna: &mut DNA, tpl: Template, e: Environment) {
+    fn replace(tpl: Template, e: Environment) -> DNA {
         let mut r = DNA::default();
         for t in tpl {
             match t {
@@ -304,6 +522,7 @@ mod jonas_matchreplace {
                 }
             }
         }
+        r
     }
 
     fn protect(mut l: usize, mut d: DNA) -> DNA {
@@ -334,7 +553,7 @@ mod jonas_matchreplace {
     fn asnat(mut n: usize) -> DNA {
         let mut result = DNA::default();
         while n > 0 {
-            result.append(if n % 2 == 0 { Base::I } else { Base::C });
+            result.append(if n.is_multiple_of(2) { Base::I } else { Base::C });
             n /= 2;
         }
         result.append(Base::P);
@@ -344,6 +563,7 @@ mod jonas_matchreplace {
     #[cfg(test)]
     mod test {
         use super::*;
+        use crate::execute::test::decode;
 
         #[test]
         fn test_asnat_jonas() {
@@ -354,19 +574,23 @@ mod jonas_matchreplace {
 
             // Test that `asnat` is the right inverse of `nat`
             for i in 0 .. 10 {
-                let mut dna = asnat(i);
-                assert_eq!(nat(&mut dna), Ok(i));
-                assert_eq!(dna, "".into());
+                assert_eq!(decode(asnat(i), |c| nat(c)), (Ok(i), "".into()));
             }
         }
     }
 }
 
 
-fn asnat(mut n: usize) -> DNA {
+/// The bases encoding the number `n`, as `asnat` in the specification.
+pub fn asnat(n: usize) -> DNA {
     let mut r = DNA::default();
+    asnat_into(n, &mut r);
+    r
+}
+
+fn asnat_into(mut n: usize, r: &mut DNA) {
     while n > 0 {
-        if n % 2 == 0 { // Even
+        if n.is_multiple_of(2) { // Even
             r.append(Base::I);
         } else {
             r.append(Base::C);
@@ -374,61 +598,109 @@ fn asnat(mut n: usize) -> DNA {
         n /= 2;
     }
     r.append(Base::P);
-    r
 }
 
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::piece_table::PieceTable;
+    use proptest::prelude::*;
 
     #[test]
     fn test_nat() {
-        assert_eq!(nat(&mut "P".into()), Ok(0));
-        assert_eq!(nat(&mut "IP".into()), Ok(0));
-        assert_eq!(nat(&mut "CP".into()), Ok(1));
-        assert_eq!(nat(&mut "ICFCP".into()), Ok(2 | 8));
-        assert_eq!(nat(&mut "ICFCIIIIP".into()), Ok(2 | 8));
-        assert_eq!(nat(&mut "CIICICP".into()), Ok(1 | 8 | 32));
+        assert_eq!(decode("P", |c| nat(c)).0, Ok(0));
+        assert_eq!(decode("IP", |c| nat(c)).0, Ok(0));
+        assert_eq!(decode("CP", |c| nat(c)).0, Ok(1));
+        assert_eq!(decode("ICFCP", |c| nat(c)).0, Ok(2 | 8));
+        assert_eq!(decode("ICFCIIIIP", |c| nat(c)).0, Ok(2 | 8));
+        assert_eq!(decode("CIICICP", |c| nat(c)).0, Ok(1 | 8 | 32));
     }
 
     #[test]
-    fn test_consts() {
-        assert_eq!(consts(&mut "".into()), "".into());
+    fn test_nat_overflow() {
+        let bits = usize::BITS as usize;
+        let zeros = |n| "I".repeat(n);
+
+        // All bits set
+        assert_eq!(decode(format!("{}P", "C".repeat(bits)).as_str(), |c| nat(c)).0, Ok(usize::MAX));
+        // Leading zeros beyond the width of `usize` are harmless
+        assert_eq!(decode(format!("C{}P", zeros(200)).as_str(), |c| nat(c)).0, Ok(1));
+        // Any higher bit saturates
+        assert_eq!(decode(format!("{}CP", zeros(bits)).as_str(), |c| nat(c)).0, Ok(usize::MAX));
+        assert_eq!(decode(format!("C{}CIP", zeros(bits + 70)).as_str(), |c| nat(c)).0, Ok(usize::MAX));
+
+        // The remaining DNA is intact
+        let dna = format!("{}CPICFP", zeros(1000));
+        assert_eq!(decode(dna.as_str(), |c| nat(c)), (Ok(usize::MAX), "ICFP".into()));
+
+        // Still no number without a terminating `P`
+        assert_eq!(decode(format!("{}C", zeros(bits)).as_str(), |c| nat(c)).0, Err(Finish));
+    }
 
-        let mut dna = "CFPIC".into();
-        assert_eq!(consts(&mut dna), "ICFP".into());
-        assert_eq!(dna, "".into());
+    #[test]
+    fn test_step_oversized_skip() {
+        // Pattern `!n` for an n beyond `usize`, with an empty template
+        let mut dna: DNA = format!("IP{}CP IIC IIC ICFP", "I".repeat(100)).as_str().into();
+        assert_eq!(step(&mut dna, &mut noop, &mut ()), Ok(()));
+        assert_eq!(dna, "ICFP".into());
+
+        // Pattern `(!1)` then the template `|n|` and reference `n_0` for
+        // oversized n, both referring to the empty DNA
+        let huge = format!("{}CP", "I".repeat(100));
+        let mut dna: DNA = format!("IIP IPCP IIC IIC IIP{} IFP{} IIC ICFP", huge, huge).as_str().into();
+        assert_eq!(step(&mut dna, &mut noop, &mut ()), Ok(()));
+        assert_eq!(dna, "PCFP".into());
+    }
 
-        // Test replacement of one base
-        let mut dna = "CFI".into();
-        assert_eq!(consts(&mut dna), "IC".into());
-        assert_eq!(dna, "I".into());
+    #[test]
+    fn test_consts() {
+        assert_eq!(decode("", |c| consts(c)), ("".into(), "".into()));
+        assert_eq!(decode("CFPIC", |c| consts(c)), ("ICFP".into(), "".into()));
 
-        // Test replacement of two bases at EOF
-        let mut dna = "CFIF".into();
-        assert_eq!(consts(&mut dna), "IC".into());
-        assert_eq!(dna, "IF".into());
+        // Stops before a lone `I` at EOF
+        assert_eq!(decode("CFI", |c| consts(c)), ("IC".into(), "I".into()));
 
-        // Test replacement of two bases before EOF
-        let mut dna = "CFIPC".into();
-        assert_eq!(consts(&mut dna), "IC".into());
-        assert_eq!(dna, "IPC".into());
+        // Stops before an `I` not followed by `C`, at EOF or not
+        assert_eq!(decode("CFIF", |c| consts(c)), ("IC".into(), "IF".into()));
+        assert_eq!(decode("CFIPC", |c| consts(c)), ("IC".into(), "IPC".into()));
     }
 
     fn noop(_: DNA) {}
 
+    /// Whether the next step of `dna` protects no group 8 times or more. Deep
+    /// protection of a non-empty group takes forever.
+    fn shallow(dna: &DNA) -> bool {
+        let mut c = dna.cursor(0);
+        pattern(&mut c, &mut noop)
+            .and_then(|_| template(&mut c, &mut noop))
+            .map_or(true, |t| t.iter().all(|t| match t {
+                TItem::Ref { l, .. } => *l < 8,
+                _ => true,
+            }))
+    }
+
+    /// Runs `f` on a cursor over `dna`, returning its result and the bases
+    /// after the ones it read.
+    pub(super) fn decode<T>(dna: impl Into<DNA>, f: impl FnOnce(&mut Cursor) -> T) -> (T, DNA) {
+        let dna = dna.into();
+        let mut c = dna.cursor(0);
+        let t = f(&mut c);
+        let pos = c.pos();
+        (t, dna.subseq(pos, dna.len()))
+    }
+
     #[test]
     fn test_pattern() {
         // Test 1 from spec
         assert_eq!(
-            pattern(&mut "CIIC".into(), &mut noop),
+            decode("CIIC", |c| pattern(c, &mut noop)).0,
             Ok(vec![PItem::Base(Base::I)])
         );
 
         // Test 2 from spec
         assert_eq!(
-            pattern(&mut "IIP IPICP IIC IC IIF".into(), &mut noop),
+            decode("IIP IPICP IIC IC IIF", |c| pattern(c, &mut noop)).0,
             Ok(vec![
                 PItem::Open(),
                 PItem::Skip(2),
@@ -441,44 +713,44 @@ mod test {
         // comes a sequence of escaped bases followed by I[IFP] as a terminator,
         // which doubles as the start of the next pattern item!
         assert_eq!(
-            pattern(&mut "IFC() IP(P) IIF".into(), &mut noop),
+            decode("IFC() IP(P) IIF", |c| pattern(c, &mut noop)).0,
             Ok(vec![PItem::Search("".into()), PItem::Skip(0)])
         );
 
         // Same example as above but with non-trivial literals.
         assert_eq!(
-            pattern(&mut "IFI(C F P IC) IP(CP) IIF".into(), &mut noop),
+            decode("IFI(C F P IC) IP(CP) IIF", |c| pattern(c, &mut noop)).0,
             Ok(vec![PItem::Search("ICFP".into()), PItem::Skip(1)])
         );
 
         let mut rna = vec![];
-        let t = pattern(&mut "P III(ICFPICF) IC IIC".into(), &mut |x| rna.push(x));
+        let (t, _) = decode("P III(ICFPICF) IC IIC", |c| pattern(c, &mut |x| rna.push(x)));
         assert_eq!(t, Ok(vec![PItem::Base(Base::F), PItem::Base(Base::P)]));
         assert_eq!(rna, vec!["ICFPICF".into()]);
     }
 
     #[test]
     fn test_template() {
-        assert_eq!(template(&mut "".into(), &mut noop), Err(Finish));
+        assert_eq!(decode("", |c| template(c, &mut noop)).0, Err(Finish));
 
         assert_eq!(
-            template(&mut "IF(P,CP) IIP(ICP) IIF".into(), &mut noop),
+            decode("IF(P,CP) IIP(ICP) IIF", |c| template(c, &mut noop)).0,
             Ok(vec![TItem::Ref { l: 0, n: 1 }, TItem::RefLen(2)])
         );
 
         let mut rna = vec![];
-        let t = template(&mut "C III(ICFPICF) F IIC".into(), &mut |x| rna.push(x));
+        let (t, _) = decode("C III(ICFPICF) F IIC", |c| template(c, &mut |x| rna.push(x)));
         assert_eq!(t, Ok(vec![TItem::Base(Base::I), TItem::Base(Base::C)]));
         assert_eq!(rna, vec!["ICFPICF".into()]);
     }
 
     #[test]
     fn test_asnat() {
-        assert_eq!(nat(&mut asnat(0)), Ok(0));
-        assert_eq!(nat(&mut asnat(1)), Ok(1));
-        assert_eq!(nat(&mut asnat(2)), Ok(2));
-        assert_eq!(nat(&mut asnat(9)), Ok(9));
-        assert_eq!(nat(&mut asnat(9384)), Ok(9384));
+        assert_eq!(decode(asnat(0), |c| nat(c)).0, Ok(0));
+        assert_eq!(decode(asnat(1), |c| nat(c)).0, Ok(1));
+        assert_eq!(decode(asnat(2), |c| nat(c)).0, Ok(2));
+        assert_eq!(decode(asnat(9), |c| nat(c)).0, Ok(9));
+        assert_eq!(decode(asnat(9384), |c| nat(c)).0, Ok(9384));
     }
 
     #[test]
@@ -496,21 +768,113 @@ mod test {
         assert_eq!(protect(2, "ICFP".into()), "FPICCF".into());
     }
 
+    #[test]
+    fn test_protect_deep() {
+        // Deeper than the memoized levels, which stay capped
+        let l = MAX_QUOTED_LEVEL + 5;
+        let mut q: DNA = "ICFP".into();
+        for _ in 0..l {
+            q = quote(q);
+        }
+        assert_eq!(protect(l, "ICFP".into()), q);
+        assert_eq!(QUOTED.with(|quoted| quoted.borrow().len()), MAX_QUOTED_LEVEL + 1);
+    }
+
     #[test]
     fn test_step() {
         let mut dna : DNA = "IIPIPICPIICICIIFICCIFPPIICCFPC".into();
         let mut rna_sink = |rna| ();
-        step(&mut dna, &mut rna_sink);
+        step(&mut dna, &mut rna_sink, &mut ());
         assert_eq!(dna, "PICFC".into());
         
         let mut dna : DNA = "IIPIPICPIICICIIFICCIFCCCPPIICCFPC".into();
         let mut rna_sink = |rna| ();
-        step(&mut dna, &mut rna_sink);
+        step(&mut dna, &mut rna_sink, &mut ());
         assert_eq!(dna, "PIICCFCFFPC".into());
         
         let mut dna : DNA = "IIPIPIICPIICIICCIICFCFC".into();
         let mut rna_sink = |rna| ();
-        step(&mut dna, &mut rna_sink);
+        step(&mut dna, &mut rna_sink, &mut ());
         assert_eq!(dna, "I".into());
     }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn test_serde() {
+        let pattern = vec![PItem::Open(), PItem::Search("ICFP".into()), PItem::Close(), PItem::Skip(3)];
+        let json = serde_json::to_string(&pattern).unwrap();
+        assert_eq!(json, r#"[{"Open":[]},{"Search":"ICFP"},{"Close":[]},{"Skip":3}]"#);
+        assert_eq!(serde_json::from_str::<Pattern>(&json).unwrap(), pattern);
+
+        let template = vec![TItem::Base(Base::P), TItem::Ref { n: 0, l: 2 }, TItem::RefLen(1)];
+        let json = serde_json::to_string(&template).unwrap();
+        assert_eq!(json, r#"[{"Base":"P"},{"Ref":{"n":0,"l":2}},{"RefLen":1}]"#);
+        assert_eq!(serde_json::from_str::<Template>(&json).unwrap(), template);
+    }
+
+    #[test]
+    fn test_step_finish() {
+        // Running out of DNA in the template leaves the DNA as it was, though
+        // RNA before that point is still emitted
+        let mut dna: DNA = "IIPIPICPIIC III(ICFPICF) IFP".into();
+        let mut rna = vec![];
+        assert_eq!(step(&mut dna, &mut |x| rna.push(x), &mut ()), Err(Finish));
+        assert_eq!(dna, "IIPIPICPIIC III(ICFPICF) IFP".into());
+        assert_eq!(rna, vec!["ICFPICF".into()]);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_nat_asnat(n: usize) {
+            prop_assert_eq!(decode(asnat(n), |c| nat(c)), (Ok(n), "".into()));
+        }
+
+        #[test]
+        fn prop_protect_quote(s in "[ICFP]{0,30}", l in 0usize..12) {
+            let d: DNA = s.as_str().into();
+            let mut q = d.clone();
+            for _ in 0..l {
+                q = quote(q);
+            }
+            prop_assert_eq!(protect(l, d), q);
+        }
+
+        #[test]
+        fn prop_consts_quote(s in "[ICFP]{0,30}") {
+            // `consts` is the inverse of `quote`
+            let d: DNA = s.as_str().into();
+            prop_assert_eq!(decode(quote(d.clone()), |c| consts(c)), (d, "".into()));
+        }
+
+        #[test]
+        fn prop_step_no_panic(s in "[ICFP]{0,60}") {
+            let mut dna: DNA = s.as_str().into();
+            for _ in 0..10 {
+                if !shallow(&dna) || step(&mut dna, &mut noop, &mut ()).is_err() {
+                    break;
+                }
+            }
+        }
+
+        #[test]
+        fn prop_backends_agree(s in "[ICFP]{0,60}") {
+            let mut dna: DNA = s.as_str().into();
+            let mut table = PieceTable::from_dna(dna.clone());
+            for _ in 0..10 {
+                if !shallow(&dna) {
+                    break;
+                }
+                let mut rna = vec![];
+                let mut table_rna = vec![];
+                let finished = step(&mut dna, &mut |r| rna.push(r), &mut ());
+                let table_finished = step_backend(&mut table, &mut |r| table_rna.push(r));
+                prop_assert_eq!(table_finished, finished);
+                prop_assert_eq!(table_rna, rna);
+                if finished.is_err() {
+                    break;
+                }
+                prop_assert_eq!(table.to_dna(), dna.clone());
+            }
+        }
+    }
 }
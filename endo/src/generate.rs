@@ -0,0 +1,244 @@
+//! Random DNA programs that are well formed and terminate, for fuzzing,
+//! comparing backends and benchmarking without the real DNA.
+//!
+//! A program is a list of instructions, an instruction that halts, and data:
+//!
+//! ```text
+//! ( !r ) p => $0 t
+//! ...
+//! !n =>
+//! <data>
+//! ```
+//!
+//! Group 0 of each instruction is the rest of the code, which the template
+//! puts back unchanged, so the random items `p` and `t` only work on the data.
+//! The data is simulated while generating, so the last instruction knows to
+//! skip all `n` bases of it. Decoding the next instruction then runs into the
+//! end of the DNA.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::asm::{Instruction, Part};
+use crate::dna::{Base, DNA};
+use crate::execute::{matchreplace, PItem, TItem};
+
+/// RNA emitted by programs, mostly known commands so rendering has work.
+const RNA: &[&str] = &[
+    "PIPIIIC", "PIPIIIP", "PIPIICC", "PIPIIPP", "PIPIIPF", "PIIPICP", "PIIIIIP", "PCCCCCP", "PFFFFFP", "PCCIFFP",
+    "PFFICCP", "PIIPIIP", "PCCPFFP", "PFFPCCP", "PFFICCF", "ICFPICF",
+];
+
+/// Most times a random instruction is drawn before settling for one that
+/// leaves the data alone.
+const ATTEMPTS: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Instructions before the one that halts.
+    pub instructions: usize,
+    /// Length of the random data the program starts with.
+    pub data_len: usize,
+    /// Most items in each pattern and template, not counting group 0.
+    pub max_items: usize,
+    /// Most times a template quotes a group.
+    pub max_level: usize,
+    /// Instructions that would make the data longer than this are not used.
+    pub max_data_len: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config { instructions: 20, data_len: 200, max_items: 8, max_level: 2, max_data_len: 10_000 }
+    }
+}
+
+/// A generated program.
+pub struct Program {
+    pub dna: DNA,
+    /// Number of steps that succeed before execution finishes, after which
+    /// the DNA is empty.
+    pub steps: usize,
+    /// The code in front of the data, the last of which halts.
+    pub instructions: Vec<Instruction>,
+}
+
+fn random_bases(rng: &mut StdRng, len: usize) -> DNA {
+    (0..len).map(|_| Base::ALL[rng.gen_range(0..4)]).collect()
+}
+
+fn random_rna(rng: &mut StdRng) -> DNA {
+    RNA[rng.gen_range(0..RNA.len())].into()
+}
+
+/// A balanced pattern working on `data`, and its number of groups.
+fn random_pattern(rng: &mut StdRng, config: &Config, data: &DNA) -> (Vec<Part<PItem>>, usize) {
+    let mut parts = vec![];
+    let (mut open, mut groups) = (0, 0);
+    for _ in 0..rng.gen_range(0..=config.max_items) {
+        let after_search = matches!(parts.last(), Some(Part::Item(PItem::Search(_))));
+        let part = match rng.gen_range(0..6) {
+            0 if !after_search => Part::Item(PItem::Base(Base::ALL[rng.gen_range(0..4)])),
+            1 => Part::Item(PItem::Skip(rng.gen_range(0..=16))),
+            2 => {
+                // Mostly bases found in the data, so searches succeed
+                let len = rng.gen_range(1..=3);
+                let at = rng.gen_range(0..=data.len());
+                let s = if rng.gen_bool(0.8) { data.subseq(at, at + len) } else { random_bases(rng, len) };
+                Part::Item(PItem::Search(s))
+            }
+            3 => {
+                open += 1;
+                Part::Item(PItem::Open())
+            }
+            4 if open > 0 => {
+                open -= 1;
+                groups += 1;
+                Part::Item(PItem::Close())
+            }
+            _ => Part::Rna(random_rna(rng)),
+        };
+        parts.push(part);
+    }
+    for _ in 0..open {
+        parts.push(Part::Item(PItem::Close()));
+        groups += 1;
+    }
+    (parts, groups)
+}
+
+/// A template referring to the `groups` groups of its pattern, numbered from
+/// `first`.
+fn random_template(rng: &mut StdRng, config: &Config, groups: usize, first: usize) -> Vec<Part<TItem>> {
+    let mut parts = vec![];
+    for _ in 0..rng.gen_range(0..=config.max_items) {
+        let part = match rng.gen_range(0..4) {
+            1 if groups > 0 => {
+                let n = first + rng.gen_range(0..groups);
+                Part::Item(TItem::Ref { n, l: rng.gen_range(0..=config.max_level) })
+            }
+            2 if groups > 0 => Part::Item(TItem::RefLen(first + rng.gen_range(0..groups))),
+            3 => Part::Rna(random_rna(rng)),
+            _ => Part::Item(TItem::Base(Base::ALL[rng.gen_range(0..4)])),
+        };
+        parts.push(part);
+    }
+    parts
+}
+
+fn items<T: Clone>(parts: &[Part<T>]) -> Vec<T> {
+    parts
+        .iter()
+        .filter_map(|p| match p {
+            Part::Item(t) => Some(t.clone()),
+            Part::Rna(_) => None,
+        })
+        .collect()
+}
+
+/// Generates a program from `seed`. The same seed and configuration give the
+/// same program.
+pub fn program(seed: u64, config: &Config) -> Program {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let initial = random_bases(&mut rng, config.data_len);
+    let mut data = initial.clone();
+    // The pattern and template of each instruction without group 0
+    let mut bodies = vec![];
+    for _ in 0..config.instructions {
+        let mut body = (vec![], vec![]);
+        for _ in 0..ATTEMPTS {
+            let (pattern, groups) = random_pattern(&mut rng, config, &data);
+            let template = random_template(&mut rng, config, groups, 0);
+            let mut d = data.clone();
+            matchreplace(&mut d, &items(&pattern), &items(&template), &mut ());
+            if d.len() <= config.max_data_len {
+                data = d;
+                body = (pattern, template);
+                break;
+            }
+        }
+        bodies.push(body);
+    }
+
+    let halt = Instruction { pattern: vec![Part::Item(PItem::Skip(data.len()))], template: vec![] };
+    let mut code = DNA::default();
+    halt.encode(&mut code);
+    let mut instructions = vec![halt];
+    // Built from the back, since group 0 is the code after the instruction
+    for (pattern, template) in bodies.into_iter().rev() {
+        let mut i = Instruction {
+            pattern: vec![Part::Item(PItem::Open()), Part::Item(PItem::Skip(code.len())), Part::Item(PItem::Close())],
+            template: vec![Part::Item(TItem::Ref { n: 0, l: 0 })],
+        };
+        i.pattern.extend(pattern);
+        i.template.extend(template.into_iter().map(|p| match p {
+            Part::Item(TItem::Ref { n, l }) => Part::Item(TItem::Ref { n: n + 1, l }),
+            Part::Item(TItem::RefLen(n)) => Part::Item(TItem::RefLen(n + 1)),
+            p => p,
+        }));
+        let mut dna = DNA::default();
+        i.encode(&mut dna);
+        dna.concat(code);
+        code = dna;
+        instructions.push(i);
+    }
+    instructions.reverse();
+    code.concat(initial);
+    Program { dna: code, steps: config.instructions + 1, instructions }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asm::disassemble;
+    use crate::backend::Backend;
+    use crate::execute::{step, step_backend};
+    use crate::piece_table::PieceTable;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_program() {
+        let config = Config::default();
+        let p = program(1, &config);
+        assert_eq!(p.instructions.len(), config.instructions + 1);
+        // The code disassembles to the instructions
+        let mut pos = 0;
+        for i in &p.instructions {
+            let (decoded, end) = disassemble(&p.dna, pos).unwrap();
+            assert_eq!(&decoded, i);
+            pos = end;
+        }
+        assert_eq!(p.dna.len() - pos, config.data_len);
+        assert_eq!(program(1, &config).dna, p.dna);
+        assert_ne!(program(2, &config).dna, p.dna);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn prop_programs_terminate(seed: u64) {
+            let p = program(seed, &Config::default());
+            let mut dna = p.dna;
+            for _ in 0..p.steps {
+                prop_assert!(step(&mut dna, &mut |_| (), &mut ()).is_ok());
+            }
+            prop_assert!(dna.is_empty());
+            prop_assert!(step(&mut dna, &mut |_| (), &mut ()).is_err());
+        }
+
+        #[test]
+        fn prop_backends_agree_on_programs(seed: u64) {
+            let p = program(seed, &Config { instructions: 10, ..Config::default() });
+            let mut dna = p.dna;
+            let mut table = PieceTable::from_dna(dna.clone());
+            let (mut rna, mut table_rna) = (vec![], vec![]);
+            for _ in 0..p.steps {
+                step(&mut dna, &mut |r| rna.push(r), &mut ()).unwrap();
+                step_backend(&mut table, &mut |r| table_rna.push(r)).unwrap();
+                prop_assert_eq!(table.to_dna(), dna.clone());
+            }
+            prop_assert_eq!(table_rna, rna);
+        }
+    }
+}
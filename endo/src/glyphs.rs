@@ -0,0 +1,263 @@
+//! Reading the text on rendered pages without OCR. Endo draws text in a
+//! fixed-size font, so a page of text is a grid of cells that each look
+//! exactly like the same character elsewhere. Cells of pages that have been
+//! read by hand are learned as glyphs, and cells of new pages are read as the
+//! glyph they differ least from.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::render::{Bitmap, Pixel, HEIGHT, WIDTH};
+
+/// Where the character cells of a page are.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Grid {
+    /// Top left corner of the first cell.
+    pub x: usize,
+    pub y: usize,
+    pub cell_width: usize,
+    pub cell_height: usize,
+    pub cols: usize,
+    pub rows: usize,
+}
+
+impl Grid {
+    /// A grid of cells covering the rest of the image from `(x, y)`.
+    pub fn new(x: usize, y: usize, cell_width: usize, cell_height: usize) -> Self {
+        let cols = WIDTH.saturating_sub(x) / cell_width.max(1);
+        let rows = HEIGHT.saturating_sub(y) / cell_height.max(1);
+        Grid { x, y, cell_width, cell_height, cols, rows }
+    }
+
+    /// The ink of the cell in column `col` and row `row`: the pixels that are
+    /// not the most common color of the cell.
+    fn cell(&self, image: &Bitmap, col: usize, row: usize) -> Vec<bool> {
+        let (x0, y0) = (self.x + col * self.cell_width, self.y + row * self.cell_height);
+        let rgb = |p: Pixel| (p.r, p.g, p.b);
+        let pixels: Vec<_> = (y0..y0 + self.cell_height)
+            .flat_map(|y| (x0..x0 + self.cell_width).map(move |x| (x, y)))
+            .map(|(x, y)| rgb(image.get(x, y)))
+            .collect();
+        let mut counts = HashMap::new();
+        for &p in &pixels {
+            *counts.entry(p).or_insert(0) += 1;
+        }
+        // Ties broken by color, so the result doesn't depend on hash order
+        let background = counts.into_iter().max_by_key(|&(p, n)| (n, p)).map(|(p, _)| p);
+        pixels.into_iter().map(|p| Some(p) != background).collect()
+    }
+}
+
+/// Characters learned from their glyphs, all of the same size.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Dictionary {
+    width: usize,
+    height: usize,
+    glyphs: Vec<(char, Vec<bool>)>,
+}
+
+impl Dictionary {
+    /// An empty dictionary of glyphs `width` by `height` pixels, which can't
+    /// be 0.
+    pub fn new(width: usize, height: usize) -> Result<Self, String> {
+        if width == 0 || height == 0 {
+            return Err(format!("glyphs can't be {}x{}", width, height));
+        }
+        Ok(Dictionary { width, height, glyphs: vec![] })
+    }
+
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    fn check_grid(&self, grid: &Grid) -> Result<(), String> {
+        if (grid.cell_width, grid.cell_height) != (self.width, self.height) {
+            return Err(format!(
+                "the cells are {}x{} but the glyphs {}x{}",
+                grid.cell_width, grid.cell_height, self.width, self.height
+            ));
+        }
+        if grid.x + grid.cols * grid.cell_width > WIDTH || grid.y + grid.rows * grid.cell_height > HEIGHT {
+            return Err("the grid is outside the image".to_string());
+        }
+        Ok(())
+    }
+
+    /// Learns the glyphs of `image` from `text`, which has a line per row of
+    /// the grid and a character per cell. Spaces and cells without ink are
+    /// skipped. Returns the number of new glyphs.
+    pub fn learn(&mut self, image: &Bitmap, grid: &Grid, text: &str) -> Result<usize, String> {
+        self.check_grid(grid)?;
+        let before = self.glyphs.len();
+        for (row, line) in text.lines().take(grid.rows).enumerate() {
+            for (col, c) in line.chars().take(grid.cols).enumerate() {
+                let ink = grid.cell(image, col, row);
+                if c == ' ' || !ink.contains(&true) {
+                    continue;
+                }
+                match self.glyphs.iter().find(|(_, g)| *g == ink) {
+                    Some(&(known, _)) if known != c => {
+                        return Err(format!(
+                            "row {} column {} is labeled {:?} but looks like {:?}",
+                            row, col, c, known
+                        ))
+                    }
+                    Some(_) => (),
+                    None => self.glyphs.push((c, ink)),
+                }
+            }
+        }
+        Ok(self.glyphs.len() - before)
+    }
+
+    /// The text in the cells of `image`, a line per row with trailing spaces
+    /// removed. A cell that differs from every glyph in more than an eighth
+    /// of its pixels is read as `?`.
+    pub fn transcribe(&self, image: &Bitmap, grid: &Grid) -> Result<String, String> {
+        self.check_grid(grid)?;
+        let mut text = String::new();
+        for row in 0..grid.rows {
+            let mut line = String::new();
+            for col in 0..grid.cols {
+                line.push(self.read(&grid.cell(image, col, row)));
+            }
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    fn read(&self, ink: &[bool]) -> char {
+        if !ink.contains(&true) {
+            return ' ';
+        }
+        let distance = |g: &[bool]| g.iter().zip(ink).filter(|(a, b)| a != b).count();
+        match self.glyphs.iter().map(|(c, g)| (distance(g), *c)).min() {
+            Some((d, c)) if d <= ink.len() / 8 => c,
+            _ => '?',
+        }
+    }
+
+    /// Reads a dictionary in the format of `Display`.
+    pub fn parse(text: &str) -> Result<Dictionary, String> {
+        let mut lines = text.lines();
+        let size = lines.next().and_then(|l| l.strip_prefix("glyphs "));
+        let size = size.and_then(|s| s.split_once('x')).and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+        let (width, height) = size.ok_or("the first line is not `glyphs <width>x<height>`")?;
+        let mut d = Dictionary::new(width, height)?;
+        while let Some(line) = lines.next() {
+            let mut chars = line.chars();
+            let c = match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("expected a character, not {:?}", line)),
+            };
+            let mut ink = Vec::with_capacity(width * height);
+            for row in lines.by_ref().take(height) {
+                if row.len() != width || !row.chars().all(|p| p == '#' || p == '.') {
+                    return Err(format!("bad row {:?} of {:?}", row, c));
+                }
+                ink.extend(row.chars().map(|p| p == '#'));
+            }
+            if ink.len() != width * height {
+                return Err(format!("the glyph of {:?} is too short", c));
+            }
+            d.glyphs.push((c, ink));
+        }
+        Ok(d)
+    }
+}
+
+/// A `glyphs <width>x<height>` line, then for each glyph a line with the
+/// character and its rows with `#` for ink and `.` for background.
+impl fmt::Display for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "glyphs {}x{}", self.width, self.height)?;
+        for (c, ink) in &self.glyphs {
+            writeln!(f, "{}", c)?;
+            for row in ink.chunks(self.width) {
+                let row: String = row.iter().map(|&i| if i { '#' } else { '.' }).collect();
+                writeln!(f, "{}", row)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INK: Pixel = Pixel { r: 255, g: 255, b: 255, a: 255 };
+
+    /// 3x3 glyphs in 4x4 cells.
+    fn font(c: char) -> [&'static str; 3] {
+        match c {
+            'L' => ["#..", "#..", "###"],
+            'T' => ["###", ".#.", ".#."],
+            'I' => [".#.", ".#.", ".#."],
+            _ => ["...", "...", "..."],
+        }
+    }
+
+    fn page(text: &str, grid: &Grid) -> Bitmap {
+        let mut image = Bitmap::default();
+        for (row, line) in text.lines().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                for (dy, pixels) in font(c).iter().enumerate() {
+                    for (dx, p) in pixels.chars().enumerate() {
+                        if p == '#' {
+                            let x = grid.x + col * grid.cell_width + dx;
+                            let y = grid.y + row * grid.cell_height + dy;
+                            image.set(x, y, INK);
+                        }
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_learn_and_transcribe() {
+        let grid = Grid { x: 10, y: 20, cell_width: 4, cell_height: 4, cols: 5, rows: 3 };
+        let mut d = Dictionary::new(4, 4).unwrap();
+        assert_eq!(d.learn(&page("LIT\nTIL", &grid), &grid, "LIT\nTIL"), Ok(3));
+        assert_eq!(d.learn(&page("LL", &grid), &grid, "TT").map_err(|_| ()), Err(()));
+
+        let mut other = page("TILL\n\n I", &grid);
+        // A damaged L is still an L, an unknown glyph isn't
+        other.set(grid.x + 12, grid.y, Pixel::default());
+        other.set(grid.x + 16 + 1, grid.y + 1, INK);
+        other.set(grid.x + 16 + 3, grid.y + 3, INK);
+        let blob = Grid { x: grid.x + 16, ..grid };
+        for (x, y) in [(0, 1), (1, 0), (2, 1), (2, 2), (1, 2), (3, 1)] {
+            other.set(blob.x + x, blob.y + y, INK);
+        }
+        assert_eq!(d.transcribe(&other, &grid), Ok("TILL?\n\n I\n".to_string()));
+
+        let small = Grid { cell_width: 3, ..grid };
+        assert!(d.transcribe(&other, &small).is_err());
+    }
+
+    #[test]
+    fn test_dictionary_format() {
+        let grid = Grid::new(0, 0, 4, 4);
+        assert_eq!((grid.cols, grid.rows), (150, 150));
+        let mut d = Dictionary::new(4, 4).unwrap();
+        d.learn(&page("LIT", &grid), &grid, "LIT").unwrap();
+        let text = d.to_string();
+        assert!(text.starts_with("glyphs 4x4\nL\n#...\n#...\n###.\n....\nI\n"));
+        assert_eq!(Dictionary::parse(&text), Ok(d));
+
+        assert!(Dictionary::parse("glyphs 2x\n").is_err());
+        assert!(Dictionary::parse("glyphs 2x2\nA\n#.\n").is_err());
+        assert!(Dictionary::parse("glyphs 2x2\nA\n#.\n#x\n").is_err());
+        assert!(Dictionary::parse("glyphs 0x2\nA\n\n\n").is_err());
+        assert!(Dictionary::parse("glyphs 2x0\n").is_err());
+        assert!(Dictionary::new(0, 0).is_err());
+    }
+}
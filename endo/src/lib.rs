@@ -0,0 +1,24 @@
+#![allow(unused)]
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod asm;
+pub mod backend;
+pub mod bucket;
+pub mod cache;
+pub mod catalog;
+pub mod diff;
+pub mod dna;
+pub mod dna_diff;
+pub mod execute;
+pub mod generate;
+pub mod glyphs;
+pub mod piece_table;
+pub mod pipeline;
+pub mod profile;
+pub mod render;
+pub mod replay;
+pub mod rna;
+pub mod search;
+pub mod stats;
+pub mod trace;
+pub mod viewer;
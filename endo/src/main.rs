@@ -1,50 +1,546 @@
 #![allow(unused)]
 
-mod dna;
-mod execute;
-
+use std::fs::{self, File};
 use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use structopt::StructOpt;
-use std::path::PathBuf;
-use std::fs::File;
 
-use dna::DNA;
+use endo::catalog::Catalog;
+use endo::dna::DNA;
+use endo::dna_diff::{self, Kind};
+use endo::execute::{self, Monitor};
+use endo::glyphs::{Dictionary, Grid};
+use endo::render::Bitmap;
+use endo::search::{self, Grammar};
+use endo::{asm, cache, diff, pipeline, profile, replay, stats, trace, viewer};
+
+/// The DNA to execute, shared by the subcommands that execute it.
+#[derive(StructOpt, Debug)]
+struct Input {
+    #[structopt(name = "DNA", default_value = "endo.dna", parse(from_os_str))]
+    dna: PathBuf,
+
+    /// Bases to put in front of the DNA
+    #[structopt(long, default_value = "")]
+    prefix: DNA,
+}
+
+impl Input {
+    fn read(&self) -> DNA {
+        //TODO: Read from the zip-file directly
+        let contents = fs::read_to_string(&self.dna).expect("Unable to read the DNA");
+        let mut dna = self.prefix.clone();
+        dna.concat(contents.as_str().into());
+        dna
+    }
+}
+
+/// The cells of a page that glyphs are in.
+#[derive(StructOpt, Debug)]
+struct GridOpt {
+    /// Left edge of the first cell
+    #[structopt(long, default_value = "0")]
+    x: usize,
+
+    /// Top edge of the first cell
+    #[structopt(long, default_value = "0")]
+    y: usize,
+
+    #[structopt(long)]
+    cell_width: usize,
+
+    #[structopt(long)]
+    cell_height: usize,
+
+    /// Number of columns, by default as many as fit
+    #[structopt(long)]
+    cols: Option<usize>,
+
+    /// Number of rows, by default as many as fit
+    #[structopt(long)]
+    rows: Option<usize>,
+}
+
+impl GridOpt {
+    fn grid(&self) -> Grid {
+        let grid = Grid::new(self.x, self.y, self.cell_width, self.cell_height);
+        Grid { cols: self.cols.unwrap_or(grid.cols), rows: self.rows.unwrap_or(grid.rows), ..grid }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum GlyphsCommand {
+    /// Adds the glyphs of a page to the dictionary, creating it if needed
+    Learn {
+        #[structopt(flatten)]
+        grid: GridOpt,
+
+        #[structopt(long, default_value = "glyphs.txt", parse(from_os_str))]
+        dictionary: PathBuf,
+
+        /// A PNG of the page
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+
+        /// The text of the page, a line per row of cells
+        #[structopt(parse(from_os_str))]
+        text: PathBuf,
+    },
+    /// Prints the text of pages
+    Read {
+        #[structopt(flatten)]
+        grid: GridOpt,
+
+        #[structopt(long, default_value = "glyphs.txt", parse(from_os_str))]
+        dictionary: PathBuf,
 
-use crossbeam_channel::unbounded;
+        /// PNGs of the pages
+        #[structopt(parse(from_os_str))]
+        images: Vec<PathBuf>,
+    },
+}
 
-// Struct for command line parsing 
 #[derive(StructOpt, Debug)]
 #[structopt()]
-struct MyOpt {
-    #[structopt(name = "DNA", default_value = "numbers.json", parse(from_os_str))]
-    dna: PathBuf,
+enum Command {
+    /// Executes the DNA, rendering the RNA
+    Run {
+        #[structopt(flatten)]
+        input: Input,
+
+        /// Profile execution, attributing time to regions of this many bases
+        /// of the original DNA
+        #[structopt(long, value_name = "REGION_SIZE")]
+        profile: Option<usize>,
+
+        /// Cache up to this many decoded patterns and templates, and report
+        /// the hit rate
+        #[structopt(long, value_name = "ENTRIES")]
+        cache: Option<usize>,
+
+        /// Print a hash of the execution so far every this many steps, and at
+        /// the end, to compare runs of different versions of the interpreter
+        #[structopt(long, value_name = "INTERVAL")]
+        replay_hash: Option<u64>,
+
+        /// Show the progress of the run on http://localhost:PORT
+        #[structopt(long, value_name = "PORT")]
+        serve: Option<u16>,
+
+        /// Write the rendered image as a PNG to this file
+        #[structopt(long, short, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Executes the DNA and writes the image as a PNG
+    Render {
+        #[structopt(flatten)]
+        input: Input,
+
+        #[structopt(long, short, parse(from_os_str))]
+        output: PathBuf,
+
+        /// Stop after this many steps
+        #[structopt(long)]
+        max_steps: Option<u64>,
+    },
+    /// Prints the instructions at an offset of the DNA
+    Disasm {
+        #[structopt(flatten)]
+        input: Input,
+
+        /// Where the first instruction starts, counting the prefix
+        #[structopt(long, default_value = "0")]
+        offset: usize,
+
+        /// Number of instructions to print
+        #[structopt(long, default_value = "20")]
+        count: usize,
+    },
+    /// Prints the DNA of instructions in the format of `disasm`
+    Asm {
+        /// The instructions, by default read from standard input
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+    /// Prints the risk of the prefix: ten per pixel that differs from the
+    /// target, plus the length of the prefix
+    Score {
+        #[structopt(flatten)]
+        input: Input,
+
+        #[structopt(long, default_value = "task_desc/target.png", parse(from_os_str))]
+        target: PathBuf,
+
+        /// Write an image of where the rendered image differs from the target
+        /// to this file
+        #[structopt(long, parse(from_os_str))]
+        diff: Option<PathBuf>,
+
+        /// Write a heat map of differences per tile to this file
+        #[structopt(long, parse(from_os_str))]
+        heat_map: Option<PathBuf>,
+
+        /// Stop after this many steps
+        #[structopt(long)]
+        max_steps: Option<u64>,
+    },
+    /// Prints what each step does
+    Trace {
+        #[structopt(flatten)]
+        input: Input,
+
+        /// Number of steps to trace
+        #[structopt(long, default_value = "100")]
+        steps: u64,
+    },
+    /// Writes statistics about the execution as JSON
+    Stats {
+        #[structopt(flatten)]
+        input: Input,
+
+        /// Number of steps between samples of the DNA length
+        #[structopt(long, default_value = "1000")]
+        interval: u64,
+
+        /// The file to write to, by default standard output
+        #[structopt(long, short, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Searches for a prefix rendering close to the target image by hill
+    /// climbing
+    Search {
+        /// The operations prefixes are made of, see `endo::search::Grammar`
+        #[structopt(name = "GRAMMAR", parse(from_os_str))]
+        grammar: PathBuf,
+
+        // The DNA the prefixes are put in front of
+        #[structopt(flatten)]
+        input: Input,
+
+        #[structopt(long, default_value = "task_desc/target.png", parse(from_os_str))]
+        target: PathBuf,
+
+        /// Seed of the random choices, so a search can be repeated
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+
+        #[structopt(long, default_value = "100")]
+        iterations: u64,
+
+        /// Stop executing a candidate after this many steps
+        #[structopt(long)]
+        max_steps: Option<u64>,
+
+        /// Log improvements to this file as well as standard error
+        #[structopt(long, parse(from_os_str))]
+        log: Option<PathBuf>,
+    },
+    /// Renders every page of a catalog to a PNG, with an index page to browse
+    /// them
+    Catalog {
+        /// The pages to render, see `endo::catalog`
+        #[structopt(name = "CATALOG", parse(from_os_str))]
+        catalog: PathBuf,
+
+        // The DNA the prefixes are put in front of
+        #[structopt(flatten)]
+        input: Input,
+
+        /// Directory for the images and `index.html`
+        #[structopt(short, long, default_value = "catalog", parse(from_os_str))]
+        output: PathBuf,
+
+        /// Stop executing a page after this many steps
+        #[structopt(long)]
+        max_steps: Option<u64>,
+    },
+    /// Learns the glyphs of Endo's font from pages read by hand, and reads the
+    /// text of other pages with them
+    Glyphs(GlyphsCommand),
+    /// Prints the inserted, deleted and replaced regions between two DNA files
+    #[structopt(name = "dnadiff")]
+    DnaDiff {
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+
+        /// Also print the bases of regions up to this long
+        #[structopt(long, default_value = "0")]
+        show: usize,
+    },
+}
+
+fn write_png(path: &Path, image: &Bitmap) {
+    let file = File::create(path).expect("Unable to create the image file");
+    image.write_png(BufWriter::new(file)).expect("Unable to write the image");
+}
+
+fn read_png(path: &Path) -> Bitmap {
+    let file = File::open(path).unwrap_or_else(|e| panic!("Unable to open {}: {}", path.display(), e));
+    Bitmap::read_png(BufReader::new(file)).unwrap_or_else(|e| panic!("Unable to read {}: {}", path.display(), e))
+}
+
+fn read_dna(path: &Path) -> DNA {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path.display(), e));
+    text.as_str().into()
+}
+
+fn read_dictionary(path: &Path) -> Dictionary {
+    let text = fs::read_to_string(path).expect("Unable to read the dictionary");
+    Dictionary::parse(&text).unwrap_or_else(|e| panic!("Bad dictionary: {}", e))
+}
+
+/// Writes to standard error and, if given, a file.
+struct Log(Option<File>);
+
+impl Write for Log {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(f) = &mut self.0 {
+            f.write_all(buf)?;
+        }
+        io::stderr().write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(f) = &mut self.0 {
+            f.flush()?;
+        }
+        io::stderr().flush()
+    }
 }
 
 fn main() {
-    // Parse command line arguments according to the struct
-    let opt = MyOpt::from_args();
+    // Parse command line arguments according to the enum
+    match Command::from_args() {
+        Command::Run { input, profile, cache, replay_hash, serve, output } => {
+            run(input.read(), profile, cache, replay_hash, serve, output)
+        }
+        Command::Render { input, output, max_steps } => {
+            let (image, timings) = pipeline::render(input.read(), max_steps);
+            eprint!("{}", timings);
+            write_png(&output, &image);
+        }
+        Command::Disasm { input, offset, count } => {
+            let dna = input.read();
+            let mut pos = offset;
+            for _ in 0..count {
+                if pos >= dna.len() {
+                    break;
+                }
+                match asm::disassemble(&dna, pos) {
+                    Ok((instruction, end)) => {
+                        println!("{}: {}", pos, instruction);
+                        pos = end;
+                    }
+                    Err(_) => {
+                        println!("# {}: the DNA ends inside an instruction", pos);
+                        break;
+                    }
+                }
+            }
+        }
+        Command::Asm { file } => {
+            let mut text = String::new();
+            match file {
+                Some(path) => text = fs::read_to_string(path).expect("Unable to read the instructions"),
+                None => {
+                    io::stdin().read_to_string(&mut text).expect("Unable to read the instructions");
+                }
+            }
+            match asm::assemble(&text) {
+                Ok(dna) => println!("{}", dna),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Score { input, target, diff, heat_map, max_steps } => {
+            let (image, _) = pipeline::render(input.read(), max_steps);
+            let target = read_png(&target);
+            let d = diff::Diff::new(&image, &target);
+            d.write_summary(&mut io::stderr()).unwrap();
+            println!("{}", d.risk(input.prefix.len()));
+            if let Some(path) = diff {
+                write_png(&path, &d.image(&target));
+            }
+            if let Some(path) = heat_map {
+                write_png(&path, &d.heat_map());
+            }
+        }
+        Command::Trace { input, steps } => {
+            let mut dna = input.read();
+            let stdout = io::stdout();
+            let mut trace = trace::Trace::new(BufWriter::new(stdout.lock()));
+            for _ in 0..steps {
+                if execute::step(&mut dna, &mut |_| (), &mut trace).is_err() {
+                    break;
+                }
+            }
+        }
+        Command::Stats { input, interval, output } => {
+            let mut stats = stats::Stats::new(interval);
+            execute::execute_monitored(input.read(), |_| (), &mut stats);
+            match output {
+                Some(path) => {
+                    let mut file = File::create(path).expect("Unable to create the stats file");
+                    stats.write_json(&mut file).expect("Unable to write the stats file");
+                }
+                None => stats.write_json(&mut io::stdout()).expect("Unable to write the stats"),
+            }
+        }
+        Command::Search { grammar, input, target, seed, iterations, max_steps, log } => {
+            let grammar = fs::read_to_string(&grammar).expect("Unable to read the grammar");
+            let grammar = Grammar::parse(&grammar).unwrap_or_else(|e| panic!("Bad grammar: {}", e));
+            let dna = input.read();
+            let target = read_png(&target);
+            let mut log = Log(log.as_ref().map(|p| File::create(p).expect("Unable to create the log")));
 
-    // println!("Hello, world!");
-    //TODO: Read from the zip-file directly
-    let mut file = File::open(opt.dna).unwrap();
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).expect("Unable to read the file");
-    let mut dna: DNA = DNA::from(contents.as_str());
+            let score = |prefix: &DNA| {
+                let mut d = prefix.clone();
+                d.concat(dna.clone());
+                let (image, _) = pipeline::render(d, max_steps);
+                diff::Diff::new(&image, &target).risk(prefix.len())
+            };
+            let (best, score) =
+                search::hill_climb(&grammar, seed, iterations, score, &mut log).expect("Unable to write the log");
+            writeln!(log, "best score {}", score).unwrap();
+            println!("{:?}", grammar.prefix(&best));
+        }
+        Command::Catalog { catalog, input, output, max_steps } => {
+            let catalog = fs::read_to_string(&catalog).expect("Unable to read the catalog");
+            let catalog = Catalog::parse(&catalog).unwrap_or_else(|e| panic!("Bad catalog: {}", e));
+            let dna = input.read();
+            fs::create_dir_all(&output).expect("Unable to create the output directory");
 
-    // Create a channel of unbounded capacity.
-    let (s, r) = unbounded();
+            for page in &catalog.pages {
+                let mut d = page.prefix();
+                d.concat(dna.clone());
+                let (image, timings) = pipeline::render(d, max_steps);
+                write_png(&output.join(page.file_name()), &image);
+                eprintln!("{}: {} RNA in {:.3?}", page.name, timings.rna, timings.execute);
+            }
 
-    let thr = std::thread::spawn(move || {
-        loop {
-            let orna = r.recv().unwrap();
-            match orna {
-                None => return,
-                Some(dna) => println!("Got some RNA: {:?}", dna),
+            let index = File::create(output.join("index.html")).expect("Unable to create the index");
+            catalog.write_index(BufWriter::new(index)).expect("Unable to write the index");
+        }
+        Command::Glyphs(GlyphsCommand::Learn { grid, dictionary, image, text }) => {
+            let grid = grid.grid();
+            let mut d = if dictionary.exists() {
+                read_dictionary(&dictionary)
+            } else {
+                Dictionary::new(grid.cell_width, grid.cell_height).unwrap_or_else(|e| panic!("{}", e))
+            };
+            let text = fs::read_to_string(&text).expect("Unable to read the text");
+            let new = d.learn(&read_png(&image), &grid, &text).unwrap_or_else(|e| panic!("{}", e));
+            fs::write(&dictionary, d.to_string()).expect("Unable to write the dictionary");
+            eprintln!("{} new glyphs, {} in all", new, d.len());
+        }
+        Command::Glyphs(GlyphsCommand::Read { grid, dictionary, images }) => {
+            let grid = grid.grid();
+            let d = read_dictionary(&dictionary);
+            for image in images {
+                let text = d.transcribe(&read_png(&image), &grid).unwrap_or_else(|e| panic!("{}", e));
+                println!("== {}", image.display());
+                print!("{}", text);
             }
         }
+        Command::DnaDiff { old, new, show } => dna_diff(&read_dna(&old), &read_dna(&new), show),
+    }
+}
+
+fn dna_diff(old: &DNA, new: &DNA, show: usize) {
+    let start = Instant::now();
+    let edits = dna_diff::diff(old, new);
+    let elapsed = start.elapsed();
+
+    let (mut deleted, mut inserted) = (0, 0);
+    for e in &edits {
+        println!("{}", e);
+        deleted += e.old.len();
+        inserted += e.new.len();
+        if e.kind() != Kind::Inserted && e.old.len() <= show {
+            println!("  - {:?}", old.subseq(e.old.start, e.old.end));
+        }
+        if e.kind() != Kind::Deleted && e.new.len() <= show {
+            println!("  + {:?}", new.subseq(e.new.start, e.new.end));
+        }
+    }
+    eprintln!(
+        "{} edits, {} bases removed and {} added, of {} and {} bases in {:.3?}",
+        edits.len(),
+        deleted,
+        inserted,
+        old.len(),
+        new.len(),
+        elapsed
+    );
+}
+
+fn run(
+    mut dna: DNA,
+    profile: Option<usize>,
+    cache: Option<usize>,
+    replay_hash: Option<u64>,
+    serve: Option<u16>,
+    output: Option<PathBuf>,
+) {
+    let shared = std::sync::Arc::new(viewer::Shared::default());
+    let snapshot = serve.map(|_| (shared.image.clone(), std::time::Duration::from_millis(500)));
+    let mut pipeline = pipeline::Pipeline::start_with_snapshots(snapshot);
+
+    let mut profiler = profile.map(|size| profile::Profiler::new(dna.len(), size));
+    let mut monitors: Vec<&mut dyn Monitor> = vec![];
+    if let Some(ref mut profiler) = profiler {
+        monitors.push(profiler);
+    }
+    let mut replay = replay_hash.map(|n| replay::ReplayHash::new(n, io::stderr()));
+    if let Some(ref mut replay) = replay {
+        monitors.push(replay);
+    }
+    let mut viewer = serve.map(|port| {
+        viewer::serve(port, shared.clone()).expect("Unable to start the server");
+        eprintln!("Serving progress on http://localhost:{}", port);
+        viewer::Viewer::new(shared.clone())
     });
+    if let Some(ref mut viewer) = viewer {
+        monitors.push(viewer);
+    }
+
+    let mut cache = cache.map(cache::Cache::new);
+    loop {
+        let mut rna_sink = |chunk| pipeline.send(chunk);
+        let result = match cache {
+            Some(ref mut cache) => execute::step_cached(&mut dna, &mut rna_sink, &mut monitors, cache),
+            None => execute::step(&mut dna, &mut rna_sink, &mut monitors),
+        };
+        if result.is_err() {
+            break;
+        }
+        // Keeps the snapshots for --serve up to date when there is little RNA
+        pipeline.tick();
+    }
+    let (image, timings) = pipeline.finish();
+    eprint!("{}", timings);
+    if let Some(viewer) = viewer {
+        viewer.finish(&image);
+    }
+    if let Some(path) = output {
+        write_png(&path, &image);
+    }
 
-    execute::execute(dna, |chunk| s.send(Some(chunk)).unwrap());
-    s.send(None).unwrap();
-    thr.join();
+    if let Some(replay) = replay {
+        eprintln!("replay final step {} hash {:016x}", replay.steps(), replay.hash());
+    }
+    if let Some(cache) = cache {
+        eprint!("{}", cache);
+    }
+    if let Some(profiler) = profiler {
+        eprint!("{}", profiler);
+    }
 }
@@ -0,0 +1,306 @@
+//! A piece table, kept for comparison with the `im::Vector` in `DNA`. The
+//! sequence is a list of pieces of the original DNA, which is never modified,
+//! and of an append-only buffer holding every base added since.
+
+use std::cell::{Ref, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::backend::{Backend, DnaCursor};
+use crate::dna::{Base, DNA};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Piece {
+    /// Whether this is a piece of the added bases rather than the original.
+    added: bool,
+    start: usize,
+    len: usize,
+}
+
+#[derive(Clone)]
+pub struct PieceTable {
+    original: Rc<[Base]>,
+    added: Rc<RefCell<Vec<Base>>>,
+    pieces: VecDeque<Piece>,
+    len: usize,
+}
+
+impl PieceTable {
+    /// Adds `piece` at the end, merging it with the last piece if they are
+    /// adjacent.
+    fn push_piece(&mut self, piece: Piece) {
+        if piece.len == 0 {
+            return;
+        }
+        self.len += piece.len;
+        if let Some(last) = self.pieces.back_mut() {
+            if last.added == piece.added && last.start + last.len == piece.start {
+                last.len += piece.len;
+                return;
+            }
+        }
+        self.pieces.push_back(piece);
+    }
+
+    /// Number of pieces, which is what makes a piece table slow.
+    pub fn pieces(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// Number of bases added since the original DNA, shared by all tables
+    /// derived from it.
+    pub fn added_len(&self) -> usize {
+        self.added.borrow().len()
+    }
+}
+
+pub struct Cursor<'a> {
+    table: &'a PieceTable,
+    added: Ref<'a, Vec<Base>>,
+    /// The piece being read and the offset in it.
+    piece: usize,
+    offset: usize,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn base(&self, piece: &Piece, offset: usize) -> Base {
+        let i = piece.start + offset;
+        if piece.added {
+            self.added[i]
+        } else {
+            self.table.original[i]
+        }
+    }
+}
+
+impl<'a> Iterator for Cursor<'a> {
+    type Item = Base;
+
+    fn next(&mut self) -> Option<Base> {
+        let piece = *self.table.pieces.get(self.piece)?;
+        let b = self.base(&piece, self.offset);
+        self.pos += 1;
+        self.offset += 1;
+        if self.offset == piece.len {
+            self.piece += 1;
+            self.offset = 0;
+        }
+        Some(b)
+    }
+}
+
+impl<'a> DnaCursor for Cursor<'a> {
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn peek(&mut self, offset: usize) -> Option<Base> {
+        let mut offset = self.offset + offset;
+        for piece in self.table.pieces.range(self.piece..) {
+            if offset < piece.len {
+                return Some(self.base(piece, offset));
+            }
+            offset -= piece.len;
+        }
+        None
+    }
+
+    fn advance(&mut self, mut count: usize) {
+        while count > 0 {
+            let piece = match self.table.pieces.get(self.piece) {
+                Some(piece) => piece,
+                None => return,
+            };
+            let step = count.min(piece.len - self.offset);
+            self.pos += step;
+            self.offset += step;
+            count -= step;
+            if self.offset == piece.len {
+                self.piece += 1;
+                self.offset = 0;
+            }
+        }
+    }
+}
+
+impl Backend for PieceTable {
+    type Cursor<'a> = Cursor<'a>;
+
+    fn from_dna(dna: DNA) -> Self {
+        let original: Rc<[Base]> = dna.into_iter().collect();
+        let mut table = PieceTable {
+            original,
+            added: Rc::default(),
+            pieces: VecDeque::new(),
+            len: 0,
+        };
+        table.push_piece(Piece { added: false, start: 0, len: table.original.len() });
+        table
+    }
+
+    fn to_dna(&self) -> DNA {
+        let mut d = DNA::default();
+        self.cursor(0).for_each(|b| d.append(b));
+        d
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn cursor(&self, pos: usize) -> Cursor<'_> {
+        let mut c = Cursor { table: self, added: self.added.borrow(), piece: 0, offset: 0, pos: 0 };
+        c.advance(pos);
+        c
+    }
+
+    fn at(&self, index: usize) -> Option<Base> {
+        self.cursor(index).next()
+    }
+
+    fn subseq(&self, start: usize, end: usize) -> Self {
+        let end = end.min(self.len);
+        let start = start.min(end);
+        let mut sub = self.empty();
+        let mut pos = 0;
+        for piece in &self.pieces {
+            if pos >= end {
+                break;
+            }
+            let from = start.max(pos);
+            let to = end.min(pos + piece.len);
+            if from < to {
+                sub.push_piece(Piece {
+                    added: piece.added,
+                    start: piece.start + from - pos,
+                    len: to - from,
+                });
+            }
+            pos += piece.len;
+        }
+        sub
+    }
+
+    fn drop(&mut self, mut count: usize) {
+        while count > 0 {
+            match self.pieces.front_mut() {
+                None => return,
+                Some(piece) if piece.len <= count => {
+                    count -= piece.len;
+                    self.len -= piece.len;
+                    self.pieces.pop_front();
+                }
+                Some(piece) => {
+                    piece.start += count;
+                    piece.len -= count;
+                    self.len -= count;
+                    count = 0;
+                }
+            }
+        }
+    }
+
+    fn splice(&mut self, len: usize, prefix: Self) {
+        self.drop(len);
+        let mut pieces = prefix.pieces;
+        if let (Some(last), Some(first)) = (pieces.back_mut(), self.pieces.front()) {
+            if last.added == first.added && last.start + last.len == first.start {
+                last.len += first.len;
+                self.pieces.pop_front();
+            }
+        }
+        pieces.append(&mut self.pieces);
+        self.pieces = pieces;
+        self.len += prefix.len;
+    }
+
+    fn empty(&self) -> Self {
+        PieceTable {
+            original: self.original.clone(),
+            added: self.added.clone(),
+            pieces: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    fn append(&mut self, b: Base) {
+        let start = {
+            let mut added = self.added.borrow_mut();
+            added.push(b);
+            added.len() - 1
+        };
+        self.push_piece(Piece { added: true, start, len: 1 });
+    }
+
+    fn append_dna(&mut self, d: &DNA) {
+        let start = {
+            let mut added = self.added.borrow_mut();
+            added.extend(d.iter());
+            added.len() - d.len()
+        };
+        self.push_piece(Piece { added: true, start, len: d.len() });
+    }
+
+    fn concat(&mut self, rhs: Self) {
+        debug_assert!(Rc::ptr_eq(&self.added, &rhs.added));
+        for piece in rhs.pieces {
+            self.push_piece(piece);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_piece_table() {
+        let mut t = PieceTable::from_dna("ICFPICFP".into());
+        let mut r = t.empty();
+        r.append(Base::P);
+        r.concat(t.subseq(1, 3));
+        r.append_dna(&"II".into());
+        t.splice(2, r);
+        assert_eq!(t.to_dna(), "PCFIIFPICFP".into());
+        assert_eq!(t.len(), 11);
+        assert_eq!(t.at(3), Some(Base::I));
+        assert_eq!(t.find_first(&"FPIC".into(), 0), Some(9));
+        assert_eq!(t.cursor(4).read(3), "IFP".into());
+        // Adjacent pieces of the original are merged
+        assert_eq!(t.subseq(5, 11).pieces(), 1);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_same_as_dna(
+            s in "[ICFP]{0,40}",
+            ops in prop::collection::vec((0usize..50, 0usize..50, "[ICFP]{0,4}"), 0..20),
+        ) {
+            // Splice a copy of a part of itself with some new bases to the
+            // front, and compare with doing the same to a `DNA`
+            let mut dna: DNA = s.as_str().into();
+            let mut table = PieceTable::from_dna(dna.clone());
+            for (start, len, new) in ops {
+                let new: DNA = new.as_str().into();
+                let mut r = dna.subseq(start, start + len);
+                r.concat(new.clone());
+                dna.splice(len / 2, r);
+                let mut r = table.subseq(start, start + len);
+                r.append_dna(&new);
+                table.splice(len / 2, r);
+
+                prop_assert_eq!(table.len(), dna.len());
+                prop_assert_eq!(table.to_dna(), dna.clone());
+                for from in 0..4 {
+                    let needle = dna.subseq(from + 1, from + 3);
+                    prop_assert_eq!(
+                        Backend::find_first(&table, &needle, from),
+                        dna.find_first(&needle, from)
+                    );
+                }
+            }
+        }
+    }
+}
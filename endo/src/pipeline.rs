@@ -0,0 +1,252 @@
+//! Rendering of RNA on other threads while the DNA executes. RNA is sent in
+//! batches to a decoding thread, which passes the commands on to a drawing
+//! thread.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+
+use crate::dna::DNA;
+use crate::execute::step;
+use crate::render::{Bitmap, Renderer};
+use crate::rna::Command;
+
+/// RNA commands sent to the decoder at a time. Sending them one by one makes
+/// the channel the bottleneck.
+const BATCH: usize = 4096;
+
+/// Batches waiting in each channel before the sender blocks, so a renderer
+/// that falls behind slows execution down instead of piling up RNA.
+const QUEUE: usize = 16;
+
+/// Where the time of a run went. The threads run concurrently, so the
+/// durations overlap.
+#[derive(Clone, Default, Debug)]
+pub struct Timings {
+    /// From starting the pipeline until `finish` was called.
+    pub execute: Duration,
+    /// Time spent decoding RNA.
+    pub decode: Duration,
+    /// Time spent drawing, including `fill`.
+    pub render: Duration,
+    /// Time spent in flood fills.
+    pub fill: Duration,
+    /// Time spent in `finish` waiting for the renderer to catch up.
+    pub wait: Duration,
+    /// Time execution spent waiting for the decoder to take a batch.
+    pub execute_blocked: Duration,
+    /// Time the decoder spent waiting for the renderer to take a batch. If
+    /// this is most of the run, the renderer does not keep up.
+    pub decode_blocked: Duration,
+    pub rna: u64,
+    pub commands: u64,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "execute {:.3?}, decode {:.3?}, render {:.3?} (fill {:.3?}), wait {:.3?}",
+            self.execute, self.decode, self.render, self.fill, self.wait
+        )?;
+        writeln!(
+            f,
+            "blocked on a full queue: execute {:.3?}, decode {:.3?}",
+            self.execute_blocked, self.decode_blocked
+        )?;
+        writeln!(f, "{} RNA, {} known commands", self.rna, self.commands)
+    }
+}
+
+/// Renders the RNA passed to `send`. Get the image with `finish`.
+pub struct Pipeline {
+    start: Instant,
+    batch: Vec<DNA>,
+    sender: Sender<Vec<DNA>>,
+    blocked: Duration,
+    /// Partial batches are sent when the last batch is this old, if given.
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    decoder: JoinHandle<(Duration, Duration, u64)>,
+    renderer: JoinHandle<(Bitmap, Duration, Duration, u64)>,
+}
+
+impl Pipeline {
+    /// Starts the decoding and drawing threads.
+    pub fn start() -> Self {
+        Self::start_with_snapshots(None)
+    }
+
+    /// Starts the decoding and drawing threads, copying the bitmap being drawn
+    /// on into `snapshot`, if given, at most every `interval`. RNA is then
+    /// sent on at least that often, even if there is too little for a batch.
+    pub fn start_with_snapshots(snapshot: Option<(Arc<Mutex<Option<Bitmap>>>, Duration)>) -> Self {
+        let flush_interval = snapshot.as_ref().map(|(_, interval)| *interval);
+        let (sender, rna) = bounded::<Vec<DNA>>(QUEUE);
+        let (command_sender, commands) = bounded::<Vec<Command>>(QUEUE);
+
+        let decoder = thread::spawn(move || {
+            let mut busy = Duration::default();
+            let mut blocked = Duration::default();
+            let mut count = 0;
+            for batch in rna {
+                let start = Instant::now();
+                count += batch.len() as u64;
+                let decoded = batch.iter().filter_map(Command::decode).collect();
+                busy += start.elapsed();
+                let start = Instant::now();
+                command_sender.send(decoded).unwrap();
+                blocked += start.elapsed();
+            }
+            (busy, blocked, count)
+        });
+
+        let renderer = thread::spawn(move || {
+            let mut r = Renderer::default();
+            let mut busy = Duration::default();
+            let mut count = 0;
+            let mut last_snapshot = Instant::now();
+            // Whether anything was drawn since the last snapshot
+            let mut drawn = false;
+            loop {
+                let batch = match &snapshot {
+                    // Wake up now and then to snapshot what the last batch drew
+                    Some((_, interval)) => match commands.recv_timeout(*interval) {
+                        Ok(batch) => batch,
+                        Err(RecvTimeoutError::Timeout) => vec![],
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match commands.recv() {
+                        Ok(batch) => batch,
+                        Err(_) => break,
+                    },
+                };
+                let start = Instant::now();
+                count += batch.len() as u64;
+                drawn |= !batch.is_empty();
+                batch.into_iter().for_each(|c| r.apply(c));
+                busy += start.elapsed();
+                if let Some((image, interval)) = &snapshot {
+                    if drawn && last_snapshot.elapsed() >= *interval {
+                        *image.lock().unwrap() = Some(r.current().clone());
+                        last_snapshot = Instant::now();
+                        drawn = false;
+                    }
+                }
+            }
+            let fill = r.fill_time();
+            (r.finish(), busy, fill, count)
+        });
+
+        let now = Instant::now();
+        let blocked = Duration::default();
+        Pipeline { start: now, batch: vec![], sender, blocked, flush_interval, last_flush: now, decoder, renderer }
+    }
+
+    pub fn send(&mut self, rna: DNA) {
+        self.batch.push(rna);
+        if self.batch.len() >= BATCH {
+            self.flush();
+        } else {
+            self.tick();
+        }
+    }
+
+    /// Sends the RNA that is not in a batch yet if the last batch was sent
+    /// longer than the snapshot interval ago. Call it between steps, so the
+    /// snapshots keep up with execution that emits little RNA.
+    pub fn tick(&mut self) {
+        if let Some(interval) = self.flush_interval {
+            if !self.batch.is_empty() && self.last_flush.elapsed() >= interval {
+                self.flush();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        let batch = std::mem::take(&mut self.batch);
+        let start = Instant::now();
+        self.sender.send(batch).unwrap();
+        self.blocked += start.elapsed();
+    }
+
+    /// Waits for all RNA sent to be drawn, and returns the final image.
+    pub fn finish(mut self) -> (Bitmap, Timings) {
+        let execute = self.start.elapsed();
+        self.flush();
+        drop(self.sender);
+        let (decode, decode_blocked, rna) = self.decoder.join().expect("The decoder panicked");
+        let (image, render, fill, commands) = self.renderer.join().expect("The renderer panicked");
+        let wait = self.start.elapsed() - execute;
+        let execute_blocked = self.blocked;
+        (image, Timings { execute, decode, render, fill, wait, execute_blocked, decode_blocked, rna, commands })
+    }
+}
+
+/// Executes `dna`, for at most `max_steps` steps if given, and renders the
+/// RNA.
+pub fn render(mut dna: DNA, max_steps: Option<u64>) -> (Bitmap, Timings) {
+    let mut pipeline = Pipeline::start();
+    let mut steps = 0;
+    while max_steps.is_none_or(|n| steps < n) {
+        if step(&mut dna, &mut |rna| pipeline.send(rna), &mut ()).is_err() {
+            break;
+        }
+        steps += 1;
+    }
+    pipeline.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::Pixel;
+
+    #[test]
+    fn test_pipeline() {
+        let mut p = Pipeline::start();
+        // Fill with red, with unknown RNA in between
+        for rna in &["PIPIIIP", "IIIIIII", "PIIPIIP"] {
+            p.send((*rna).into());
+        }
+        let (image, timings) = p.finish();
+        assert_eq!((timings.rna, timings.commands), (3, 2));
+        assert_eq!(image.get(599, 599), Pixel { r: 255, g: 0, b: 0, a: 255 });
+    }
+
+    #[test]
+    fn test_pipeline_full_queues() {
+        // More batches than both queues hold, each filling the whole image
+        let mut p = Pipeline::start();
+        let batches = 3 * QUEUE;
+        for _ in 0..batches * BATCH / 2 {
+            p.send("PIPIIIP".into());
+            p.send("PIIPIIP".into());
+        }
+        let (image, timings) = p.finish();
+        assert_eq!(timings.commands, (batches * BATCH) as u64);
+        assert_eq!(image.get(0, 0), Pixel { r: 255, g: 0, b: 0, a: 255 });
+    }
+
+    #[test]
+    fn test_snapshot_partial_batch() {
+        // Far less RNA than a batch is drawn and snapshot before `finish`
+        let snapshot = Arc::new(Mutex::new(None));
+        let interval = Duration::from_millis(10);
+        let mut p = Pipeline::start_with_snapshots(Some((snapshot.clone(), interval)));
+        p.send("PIPIIIP".into());
+        p.send("PIIPIIP".into());
+        let red = Pixel { r: 255, g: 0, b: 0, a: 255 };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while snapshot.lock().unwrap().as_ref().is_none_or(|image: &Bitmap| image.get(0, 0) != red) {
+            assert!(Instant::now() < deadline, "No snapshot of the partial batch");
+            thread::sleep(interval);
+            p.tick();
+        }
+        p.finish();
+    }
+}
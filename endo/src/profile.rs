@@ -0,0 +1,324 @@
+//! Profiling of DNA execution. The time spent in each step is attributed to
+//! the region of the original DNA that the executing pattern and template were
+//! copied from, as far as that can be tracked through the rewrites.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::dna::DNA;
+use crate::execute::{Match, Monitor, PItem, TItem};
+
+/// Number of regions listed in the report.
+const HOT_REGIONS: usize = 20;
+
+/// A run of consecutive bases of the current DNA. If they are an unmodified
+/// copy of the original DNA, `origin` is the offset they started at there.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Run {
+    len: usize,
+    origin: Option<usize>,
+}
+
+/// Appends `run` to `runs`, merging it with the last run if they are adjacent.
+fn push_run(runs: &mut Vec<Run>, run: Run) {
+    if run.len == 0 {
+        return;
+    }
+    if let Some(last) = runs.last_mut() {
+        let adjacent = match (last.origin, run.origin) {
+            (None, None) => true,
+            (Some(a), Some(b)) => a + last.len == b,
+            _ => false,
+        };
+        if adjacent {
+            last.len += run.len;
+            return;
+        }
+    }
+    runs.push(run);
+}
+
+/// Maps every base of the current DNA back to its offset in the original DNA.
+#[derive(Clone, Default, Debug)]
+struct Provenance {
+    runs: VecDeque<Run>,
+}
+
+impl Provenance {
+    fn new(len: usize) -> Self {
+        let mut runs = VecDeque::new();
+        if len > 0 {
+            runs.push_back(Run { len, origin: Some(0) });
+        }
+        Provenance { runs }
+    }
+
+    /// Offset in the original DNA of the first base, if known.
+    fn origin(&self) -> Option<usize> {
+        self.runs.front().and_then(|r| r.origin)
+    }
+
+    /// The runs covering `[start, end)`. Indexes are as in `DNA::subseq`.
+    fn slice(&self, start: usize, end: usize, out: &mut Vec<Run>) {
+        let mut pos = 0;
+        for run in &self.runs {
+            if pos >= end {
+                break;
+            }
+            let from = start.max(pos);
+            let to = end.min(pos + run.len);
+            if from < to {
+                push_run(out, Run {
+                    len: to - from,
+                    origin: run.origin.map(|o| o + from - pos),
+                });
+            }
+            pos += run.len;
+        }
+    }
+
+    fn drop(&mut self, mut count: usize) {
+        while count > 0 {
+            match self.runs.front_mut() {
+                None => return,
+                Some(run) if run.len <= count => {
+                    count -= run.len;
+                    self.runs.pop_front();
+                }
+                Some(run) => {
+                    run.len -= count;
+                    run.origin = run.origin.map(|o| o + count);
+                    count = 0;
+                }
+            }
+        }
+    }
+
+    fn prepend(&mut self, runs: Vec<Run>) {
+        for run in runs.into_iter().rev() {
+            self.runs.push_front(run);
+        }
+    }
+}
+
+/// Length of `protect(l, d)`, computed without quoting.
+fn protected_len(l: usize, d: DNA) -> usize {
+    // `lens[b]` is the length of quoting the base `b` the current number of
+    // times, indexed in the order I, C, F, P.
+    let mut lens = [1usize; 4];
+    for _ in 0..l {
+        lens = [lens[1], lens[2], lens[3], lens[0].saturating_add(lens[1])];
+    }
+    d.into_iter()
+        .map(|b| lens[b as usize])
+        .fold(0, usize::saturating_add)
+}
+
+/// Length of `asnat(n)`.
+fn asnat_len(n: usize) -> usize {
+    (usize::BITS - n.leading_zeros()) as usize + 1
+}
+
+#[derive(Clone, Default, Debug)]
+struct Region {
+    steps: u64,
+    /// Decoding the pattern and the template.
+    decode: Duration,
+    matching: Duration,
+    replace: Duration,
+    copied: u64,
+}
+
+impl Region {
+    fn total(&self) -> Duration {
+        self.decode + self.matching + self.replace
+    }
+
+    fn add(&mut self, other: &Region) {
+        self.steps += other.steps;
+        self.decode += other.decode;
+        self.matching += other.matching;
+        self.replace += other.replace;
+        self.copied += other.copied;
+    }
+}
+
+/// A `Monitor` that times the phases of each step. Print it to get the report.
+pub struct Profiler {
+    region_size: usize,
+    provenance: Provenance,
+    /// Keyed by region number, or `None` for code that was generated.
+    regions: HashMap<Option<usize>, Region>,
+    current: Region,
+    current_region: Option<usize>,
+    /// The DNA and match of the current step, until the replacement is known.
+    matched: Option<(DNA, Match)>,
+    phase_start: Instant,
+}
+
+impl Profiler {
+    /// Profiles execution of a DNA of length `dna_len`, grouping offsets in
+    /// the original DNA into regions of `region_size` bases.
+    pub fn new(dna_len: usize, region_size: usize) -> Self {
+        Profiler {
+            region_size: region_size.max(1),
+            provenance: Provenance::new(dna_len),
+            regions: HashMap::new(),
+            current: Region::default(),
+            current_region: None,
+            matched: None,
+            phase_start: Instant::now(),
+        }
+    }
+
+    /// Time since the last phase ended.
+    fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.phase_start;
+        self.phase_start = now;
+        elapsed
+    }
+}
+
+impl Monitor for Profiler {
+    fn begin_step(&mut self, _dna: &DNA) {
+        self.current = Region { steps: 1, ..Region::default() };
+        self.current_region = self.provenance.origin().map(|o| o / self.region_size);
+        self.phase_start = Instant::now();
+    }
+
+    fn decoded(&mut self, _pattern: &[PItem], _template: &[TItem], len: usize) {
+        self.current.decode = self.lap();
+        self.provenance.drop(len);
+        self.phase_start = Instant::now();
+    }
+
+    fn matched(&mut self, dna: &DNA, m: Option<&Match>) {
+        self.current.matching = self.lap();
+        // Cheap to clone, and needed once the template is known
+        self.matched = m.map(|m| (dna.clone(), m.clone()));
+        self.phase_start = Instant::now();
+    }
+
+    fn replaced(&mut self, template: &[TItem], len: usize) {
+        self.current.replace = self.lap();
+        self.current.copied += len as u64;
+        if let Some((dna, m)) = self.matched.take() {
+            let mut runs = vec![];
+            for t in template {
+                let span = |n: usize| m.env.get(n).copied().unwrap_or((0, 0));
+                match *t {
+                    TItem::Base(_) => push_run(&mut runs, Run { len: 1, origin: None }),
+                    TItem::Ref { n, l: 0 } => {
+                        let (from, to) = span(n);
+                        self.provenance.slice(from, to, &mut runs);
+                    }
+                    TItem::Ref { n, l } => {
+                        let (from, to) = span(n);
+                        let len = protected_len(l, dna.subseq(from, to));
+                        push_run(&mut runs, Run { len, origin: None });
+                    }
+                    TItem::RefLen(n) => {
+                        let (from, to) = span(n);
+                        let len = asnat_len(to.saturating_sub(from));
+                        push_run(&mut runs, Run { len, origin: None });
+                    }
+                }
+            }
+            self.provenance.drop(m.len);
+            self.provenance.prepend(runs);
+        }
+        self.phase_start = Instant::now();
+    }
+
+    fn end_step(&mut self, _dna: &DNA) {
+        let lap = self.lap();
+        self.current.replace += lap;
+        let current = std::mem::take(&mut self.current);
+        self.regions
+            .entry(self.current_region)
+            .or_default()
+            .add(&current);
+    }
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut total = Region::default();
+        for r in self.regions.values() {
+            total.add(r);
+        }
+        writeln!(
+            f,
+            "{} steps: decode {:.3?}, match {:.3?}, replace {:.3?}, {} bases copied",
+            total.steps, total.decode, total.matching, total.replace, total.copied
+        )?;
+
+        let mut hot: Vec<_> = self.regions.iter().collect();
+        hot.sort_by_key(|(_, r)| std::cmp::Reverse(r.total()));
+        writeln!(
+            f,
+            "{:>19} {:>10} {:>12} {:>12} {:>12} {:>12}",
+            "region", "steps", "decode", "match", "replace", "copied"
+        )?;
+        for (key, r) in hot.into_iter().take(HOT_REGIONS) {
+            let name = match key {
+                Some(k) => format!("{}..{}", k * self.region_size, (k + 1) * self.region_size),
+                None => "generated".to_string(),
+            };
+            writeln!(
+                f,
+                "{:>19} {:>10} {:>12.3?} {:>12.3?} {:>12.3?} {:>12}",
+                name, r.steps, r.decode, r.matching, r.replace, r.copied
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::execute::protect;
+
+    #[test]
+    fn test_provenance() {
+        let mut p = Provenance::new(10);
+        p.drop(3);
+        assert_eq!(p.origin(), Some(3));
+
+        let mut runs = vec![];
+        push_run(&mut runs, Run { len: 2, origin: None });
+        p.slice(1, 3, &mut runs);
+        p.slice(3, 5, &mut runs);
+        assert_eq!(runs, vec![
+            Run { len: 2, origin: None },
+            Run { len: 4, origin: Some(4) },
+        ]);
+
+        p.drop(5);
+        p.prepend(runs);
+        assert_eq!(p.origin(), None);
+        p.drop(2);
+        assert_eq!(p.origin(), Some(4));
+        p.drop(4);
+        assert_eq!(p.origin(), Some(8));
+    }
+
+    #[test]
+    fn test_protected_len() {
+        for l in 0..5 {
+            let d: DNA = "ICFPPFCI".into();
+            let q = protect(l, d.clone());
+            assert_eq!(protected_len(l, d), q.len());
+        }
+    }
+
+    #[test]
+    fn test_asnat_len() {
+        assert_eq!(asnat_len(0), 1);
+        assert_eq!(asnat_len(1), 2);
+        assert_eq!(asnat_len(5), 4);
+    }
+}
@@ -0,0 +1,418 @@
+//! Drawing of RNA commands onto bitmaps, as described in section 4 of the
+//! specification.
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::bucket::Bucket;
+use crate::rna::Command;
+
+pub const WIDTH: usize = 600;
+pub const HEIGHT: usize = 600;
+
+/// Maximum number of bitmaps on the stack.
+const MAX_BITMAPS: usize = 10;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// A `WIDTH` by `HEIGHT` image, transparent black to begin with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    pixels: Vec<Pixel>,
+}
+
+impl Default for Bitmap {
+    fn default() -> Self {
+        Bitmap { pixels: vec![Pixel::default(); WIDTH * HEIGHT] }
+    }
+}
+
+impl Bitmap {
+    pub fn get(&self, x: usize, y: usize) -> Pixel {
+        self.pixels[y * WIDTH + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, p: Pixel) {
+        self.pixels[y * WIDTH + x] = p;
+    }
+
+    /// Replaces the area of pixels with the color at `(x, y)` by `new`, where
+    /// pixels are connected horizontally and vertically. A span of a row is
+    /// filled at a time, remembering one pixel of every span next to it to
+    /// fill later.
+    pub fn fill(&mut self, x: usize, y: usize, new: Pixel) {
+        let old = self.get(x, y);
+        if old == new {
+            return;
+        }
+        let mut seeds = vec![(x, y)];
+        while let Some((x, y)) = seeds.pop() {
+            let row = &mut self.pixels[y * WIDTH..(y + 1) * WIDTH];
+            if row[x] != old {
+                // Filled since it was seeded
+                continue;
+            }
+            let mut left = x;
+            while left > 0 && row[left - 1] == old {
+                left -= 1;
+            }
+            let mut right = x + 1;
+            while right < WIDTH && row[right] == old {
+                right += 1;
+            }
+            row[left..right].iter_mut().for_each(|p| *p = new);
+
+            let above = y.checked_sub(1);
+            let below = Some(y + 1).filter(|&y| y < HEIGHT);
+            for y in above.into_iter().chain(below) {
+                let row = &self.pixels[y * WIDTH..(y + 1) * WIDTH];
+                let mut in_span = false;
+                for (x, &p) in row.iter().enumerate().take(right).skip(left) {
+                    let inside = p == old;
+                    if inside && !in_span {
+                        seeds.push((x, y));
+                    }
+                    in_span = inside;
+                }
+            }
+        }
+    }
+
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    /// Writes the RGB values as a PNG, ignoring transparency like the final
+    /// image does.
+    pub fn write_png(&self, w: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let data: Vec<u8> = self.pixels.iter().flat_map(|p| vec![p.r, p.g, p.b]).collect();
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .map_err(io::Error::other)
+    }
+
+    /// Reads an 8-bit RGB or RGBA PNG of the right size. Pixels are opaque
+    /// unless the PNG says otherwise.
+    pub fn read_png(r: impl Read) -> io::Result<Bitmap> {
+        let (info, mut reader) = png::Decoder::new(r).read_info().map_err(io::Error::other)?;
+        if (info.width, info.height) != (WIDTH as u32, HEIGHT as u32) {
+            return Err(io::Error::other(format!("The image is {}x{}", info.width, info.height)));
+        }
+        let channels = match (info.color_type, info.bit_depth) {
+            (png::ColorType::RGB, png::BitDepth::Eight) => 3,
+            (png::ColorType::RGBA, png::BitDepth::Eight) => 4,
+            (c, d) => return Err(io::Error::other(format!("Unsupported PNG format {:?} {:?}", c, d))),
+        };
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).map_err(io::Error::other)?;
+        let pixels = data
+            .chunks(channels)
+            .map(|c| Pixel { r: c[0], g: c[1], b: c[2], a: c.get(3).copied().unwrap_or(255) })
+            .collect();
+        Ok(Bitmap { pixels })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Dir {
+    N,
+    E,
+    S,
+    W,
+}
+
+/// The state of the RNA processing. Feed it commands with `apply`.
+pub struct Renderer {
+    bucket: Bucket,
+    position: (usize, usize),
+    mark: (usize, usize),
+    dir: Dir,
+    /// The top of the stack is the last element.
+    bitmaps: Vec<Bitmap>,
+    fill_time: Duration,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer {
+            bucket: Bucket::default(),
+            position: (0, 0),
+            mark: (0, 0),
+            dir: Dir::E,
+            bitmaps: vec![Bitmap::default()],
+            fill_time: Duration::default(),
+        }
+    }
+}
+
+impl Renderer {
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::AddColor(c) => self.bucket.add(c),
+            Command::ClearBucket => self.bucket.clear(),
+            Command::Move => {
+                let (x, y) = self.position;
+                self.position = match self.dir {
+                    Dir::N => (x, (y + HEIGHT - 1) % HEIGHT),
+                    Dir::E => ((x + 1) % WIDTH, y),
+                    Dir::S => (x, (y + 1) % HEIGHT),
+                    Dir::W => ((x + WIDTH - 1) % WIDTH, y),
+                };
+            }
+            Command::TurnCounterClockwise => {
+                self.dir = match self.dir {
+                    Dir::N => Dir::W,
+                    Dir::E => Dir::N,
+                    Dir::S => Dir::E,
+                    Dir::W => Dir::S,
+                };
+            }
+            Command::TurnClockwise => {
+                self.dir = match self.dir {
+                    Dir::N => Dir::E,
+                    Dir::E => Dir::S,
+                    Dir::S => Dir::W,
+                    Dir::W => Dir::N,
+                };
+            }
+            Command::Mark => self.mark = self.position,
+            Command::Line => self.line(),
+            Command::TryFill => {
+                let start = Instant::now();
+                self.try_fill();
+                self.fill_time += start.elapsed();
+            }
+            Command::AddBitmap => {
+                if self.bitmaps.len() < MAX_BITMAPS {
+                    self.bitmaps.push(Bitmap::default());
+                }
+            }
+            Command::Compose => self.combine(|top, bottom| {
+                let keep = |c: u8| (c as u32 * (255 - top.a as u32) / 255) as u8;
+                Pixel {
+                    r: top.r + keep(bottom.r),
+                    g: top.g + keep(bottom.g),
+                    b: top.b + keep(bottom.b),
+                    a: top.a + keep(bottom.a),
+                }
+            }),
+            Command::Clip => self.combine(|top, bottom| {
+                let scale = |c: u8| (c as u32 * top.a as u32 / 255) as u8;
+                Pixel {
+                    r: scale(bottom.r),
+                    g: scale(bottom.g),
+                    b: scale(bottom.b),
+                    a: scale(bottom.a),
+                }
+            }),
+        }
+    }
+
+    /// The bitmap at the top of the stack, which is the final image.
+    pub fn finish(mut self) -> Bitmap {
+        self.bitmaps.pop().expect("There is always a bitmap")
+    }
+
+    /// The bitmap being drawn on, at the top of the stack.
+    pub fn current(&self) -> &Bitmap {
+        self.bitmaps.last().expect("There is always a bitmap")
+    }
+
+    /// Total time spent in `TryFill` commands.
+    pub fn fill_time(&self) -> Duration {
+        self.fill_time
+    }
+
+    fn top(&mut self) -> &mut Bitmap {
+        self.bitmaps.last_mut().expect("There is always a bitmap")
+    }
+
+    /// Draws a line from the position to the mark, both included.
+    fn line(&mut self) {
+        let p = self.bucket.current_pixel();
+        let (x0, y0) = (self.position.0 as i64, self.position.1 as i64);
+        let (x1, y1) = (self.mark.0 as i64, self.mark.1 as i64);
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let d = dx.abs().max(dy.abs());
+        let c = if dx * dy <= 0 { 1 } else { 0 };
+        let mut x = x0 * d + (d - c).div_euclid(2);
+        let mut y = y0 * d + (d - c).div_euclid(2);
+        let top = self.top();
+        for _ in 0..d {
+            top.set(x.div_euclid(d) as usize, y.div_euclid(d) as usize, p);
+            x += dx;
+            y += dy;
+        }
+        top.set(x1 as usize, y1 as usize, p);
+    }
+
+    fn try_fill(&mut self) {
+        let new = self.bucket.current_pixel();
+        let (x, y) = self.position;
+        self.top().fill(x, y, new);
+    }
+
+    /// Replaces the two top bitmaps by combining each pair of pixels with `f`.
+    fn combine(&mut self, f: impl Fn(Pixel, Pixel) -> Pixel) {
+        if self.bitmaps.len() < 2 {
+            return;
+        }
+        let top = self.bitmaps.pop().unwrap();
+        let bottom = self.top();
+        for (b, t) in bottom.pixels.iter_mut().zip(top.pixels) {
+            *b = f(t, *b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rna::*;
+    use proptest::prelude::*;
+
+    fn render(commands: &[Command]) -> Renderer {
+        let mut r = Renderer::default();
+        commands.iter().for_each(|&c| r.apply(c));
+        r
+    }
+
+    #[test]
+    fn test_line() {
+        // From (0, 0) to (3, 1), drawn in opaque black
+        let mut r = render(&[Command::Move, Command::Move, Command::Move]);
+        r.apply(Command::TurnClockwise);
+        r.apply(Command::Move);
+        r.apply(Command::Mark);
+        r.position = (0, 0);
+        r.apply(Command::Line);
+        let black = Pixel { r: 0, g: 0, b: 0, a: 255 };
+        let top = r.finish();
+        let drawn: Vec<_> = (0..2)
+            .flat_map(|y| (0..5).map(move |x| (x, y)))
+            .filter(|&(x, y)| top.get(x, y) == black)
+            .collect();
+        assert_eq!(drawn, vec![(0, 0), (1, 0), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn test_move_wraps() {
+        let r = render(&[Command::TurnCounterClockwise, Command::Move]);
+        assert_eq!(r.position, (0, HEIGHT - 1));
+        let r = render(&[Command::TurnClockwise, Command::TurnClockwise, Command::Move]);
+        assert_eq!(r.position, (WIDTH - 1, 0));
+    }
+
+    #[test]
+    fn test_fill() {
+        // A vertical line splits the canvas, and only the left part is filled
+        let mut r = render(&[Command::AddColor(WHITE), Command::Move, Command::Move]);
+        r.apply(Command::Mark);
+        r.apply(Command::TurnClockwise);
+        for _ in 0..HEIGHT - 1 {
+            r.apply(Command::Move);
+        }
+        r.apply(Command::Line);
+        r.apply(Command::ClearBucket);
+        r.apply(Command::AddColor(RED));
+        r.position = (0, 0);
+        r.apply(Command::TryFill);
+        let top = r.finish();
+        let red = Pixel { r: 255, g: 0, b: 0, a: 255 };
+        assert_eq!(top.get(1, 599), red);
+        assert_eq!(top.get(2, 300), Pixel { r: 255, g: 255, b: 255, a: 255 });
+        assert_eq!(top.get(3, 0), Pixel::default());
+    }
+
+    /// Flood fill the way the specification describes it, but breadth first.
+    fn fill_reference(bitmap: &mut Bitmap, x: usize, y: usize, new: Pixel) {
+        let old = bitmap.get(x, y);
+        if old == new {
+            return;
+        }
+        let mut queue = std::collections::VecDeque::new();
+        bitmap.set(x, y, new);
+        queue.push_back((x, y));
+        while let Some((x, y)) = queue.pop_front() {
+            let neighbors = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for &(x, y) in &neighbors {
+                if x < WIDTH && y < HEIGHT && bitmap.get(x, y) == old {
+                    bitmap.set(x, y, new);
+                    queue.push_back((x, y));
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn prop_fill_reference(
+            seed: u64,
+            density in 0u64..100,
+            x in 0..WIDTH,
+            y in 0..HEIGHT,
+        ) {
+            // Random walls, with `density` percent of the pixels set
+            let wall = Pixel { r: 255, g: 255, b: 255, a: 255 };
+            let mut bitmap = Bitmap::default();
+            let mut state = seed;
+            for p in bitmap.pixels.iter_mut() {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                if (state >> 33) % 100 < density {
+                    *p = wall;
+                }
+            }
+            let red = Pixel { r: 255, g: 0, b: 0, a: 255 };
+            let mut expected = bitmap.clone();
+            fill_reference(&mut expected, x, y, red);
+            bitmap.fill(x, y, red);
+            prop_assert!(bitmap == expected);
+        }
+    }
+
+    #[test]
+    fn test_compose_clip() {
+        let mut r = render(&[Command::AddColor(RED), Command::TryFill, Command::AddBitmap]);
+        r.apply(Command::ClearBucket);
+        r.apply(Command::AddColor(BLUE));
+        r.apply(Command::AddColor(TRANSPARENT));
+        r.apply(Command::AddColor(OPAQUE));
+        r.apply(Command::TryFill);
+        r.apply(Command::Compose);
+        // Half-transparent blue over red
+        assert_eq!(r.bitmaps.len(), 1);
+        assert_eq!(r.top().get(0, 0), Pixel { r: 128, g: 0, b: 127, a: 255 });
+
+        r.apply(Command::AddBitmap);
+        r.apply(Command::TryFill);
+        r.apply(Command::Clip);
+        assert_eq!(r.top().get(0, 0), Pixel { r: 63, g: 0, b: 63, a: 127 });
+    }
+
+    #[test]
+    fn test_finish_top() {
+        // The bitmap added last is drawn on and is the image
+        let mut r = render(&[Command::AddBitmap, Command::AddColor(RED), Command::TryFill]);
+        assert_eq!(r.bitmaps.len(), 2);
+        let red = Pixel { r: 255, g: 0, b: 0, a: 255 };
+        assert_eq!(r.current().get(0, 0), red);
+        assert_eq!(r.finish().get(0, 0), red);
+    }
+}
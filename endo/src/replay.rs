@@ -0,0 +1,99 @@
+//! A hash of everything an execution does, for checking that a change to the
+//! interpreter does not change its behavior. Run the same DNA with the old and
+//! the new interpreter and compare the hashes printed at each checkpoint; the
+//! first checkpoint that differs narrows down where they diverge.
+
+use std::io::Write;
+
+use crate::dna::DNA;
+use crate::execute::Monitor;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// A `Monitor` folding the RNA emitted and the step number and DNA length
+/// after each step into an FNV-1a hash. Every `interval` steps, a line with
+/// the step number and the hash is written to `out`.
+pub struct ReplayHash<W: Write> {
+    interval: u64,
+    out: W,
+    steps: u64,
+    hash: u64,
+}
+
+impl<W: Write> ReplayHash<W> {
+    pub fn new(interval: u64, out: W) -> Self {
+        ReplayHash { interval: interval.max(1), out, steps: 0, hash: FNV_OFFSET }
+    }
+
+    /// The hash of the execution so far.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Number of steps finished.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn add(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.hash ^= b as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+impl<W: Write> Monitor for ReplayHash<W> {
+    fn rna(&mut self, rna: &DNA) {
+        // RNA is tagged so it can't be confused with the bytes of a number
+        self.add(b"R");
+        for &b in rna {
+            self.add(&[b as u8]);
+        }
+    }
+
+    fn end_step(&mut self, dna: &DNA) {
+        self.add(b"S");
+        self.add(&self.steps.to_le_bytes());
+        self.add(&(dna.len() as u64).to_le_bytes());
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.interval) {
+            // A checkpoint that can't be written is no reason to stop
+            let _ = writeln!(self.out, "replay step {} hash {:016x}", self.steps, self.hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::execute::execute_monitored;
+
+    fn replay(dna: &str, interval: u64) -> (u64, String) {
+        let mut replay = ReplayHash::new(interval, vec![]);
+        execute_monitored(dna.into(), |_| (), &mut replay);
+        let hash = replay.hash();
+        (hash, String::from_utf8(replay.into_inner()).unwrap())
+    }
+
+    #[test]
+    fn test_replay_hash() {
+        // Two steps, both emitting RNA, of the empty pattern and template
+        let program = "IIC III(PIPIIIC) IIC IIC III(PIPIIIP) IIC";
+        let (hash, out) = replay(program, 1);
+        assert_eq!(out.lines().count(), 2);
+        assert!(out.ends_with(&format!("replay step 2 hash {:016x}\n", hash)));
+        assert_eq!(replay(program, 2).0, hash);
+
+        // Different RNA, same lengths
+        let other = "IIC III(PIPIIIC) IIC IIC III(PIPIIIC) IIC";
+        assert_ne!(replay(other, 1).0, hash);
+        // Nothing written before the first checkpoint
+        assert_eq!(replay(program, 3).1, "");
+    }
+}
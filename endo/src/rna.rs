@@ -0,0 +1,103 @@
+//! Decoding of RNA into drawing commands.
+
+use crate::dna::{Base, DNA};
+
+/// The colors a bucket can hold.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum Color {
+    Rgb(u8, u8, u8),
+    Alpha(u8),
+}
+
+pub const BLACK: Color = Color::Rgb(0, 0, 0);
+pub const RED: Color = Color::Rgb(255, 0, 0);
+pub const GREEN: Color = Color::Rgb(0, 255, 0);
+pub const YELLOW: Color = Color::Rgb(255, 255, 0);
+pub const BLUE: Color = Color::Rgb(0, 0, 255);
+pub const MAGENTA: Color = Color::Rgb(255, 0, 255);
+pub const CYAN: Color = Color::Rgb(0, 255, 255);
+pub const WHITE: Color = Color::Rgb(255, 255, 255);
+pub const TRANSPARENT: Color = Color::Alpha(0);
+pub const OPAQUE: Color = Color::Alpha(255);
+
+/// The known RNA commands.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum Command {
+    AddColor(Color),
+    ClearBucket,
+    Move,
+    TurnCounterClockwise,
+    TurnClockwise,
+    Mark,
+    Line,
+    TryFill,
+    AddBitmap,
+    Compose,
+    Clip,
+}
+
+impl Command {
+    /// The command `rna` encodes, or `None` if it is unknown and should be
+    /// ignored.
+    pub fn decode(rna: &DNA) -> Option<Command> {
+        use Base::*;
+
+        if rna.len() != 7 {
+            return None;
+        }
+        let mut bases = [I; 7];
+        for (b, r) in bases.iter_mut().zip(rna.iter().copied()) {
+            *b = r;
+        }
+        let command = match bases {
+            [P, I, P, I, I, I, C] => Command::AddColor(BLACK),
+            [P, I, P, I, I, I, P] => Command::AddColor(RED),
+            [P, I, P, I, I, C, C] => Command::AddColor(GREEN),
+            [P, I, P, I, I, C, F] => Command::AddColor(YELLOW),
+            [P, I, P, I, I, C, P] => Command::AddColor(BLUE),
+            [P, I, P, I, I, F, C] => Command::AddColor(MAGENTA),
+            [P, I, P, I, I, F, F] => Command::AddColor(CYAN),
+            [P, I, P, I, I, P, C] => Command::AddColor(WHITE),
+            [P, I, P, I, I, P, F] => Command::AddColor(TRANSPARENT),
+            [P, I, P, I, I, P, P] => Command::AddColor(OPAQUE),
+            [P, I, I, P, I, C, P] => Command::ClearBucket,
+            [P, I, I, I, I, I, P] => Command::Move,
+            [P, C, C, C, C, C, P] => Command::TurnCounterClockwise,
+            [P, F, F, F, F, F, P] => Command::TurnClockwise,
+            [P, C, C, I, F, F, P] => Command::Mark,
+            [P, F, F, I, C, C, P] => Command::Line,
+            [P, I, I, P, I, I, P] => Command::TryFill,
+            [P, C, C, P, F, F, P] => Command::AddBitmap,
+            [P, F, F, P, C, C, P] => Command::Compose,
+            [P, F, F, I, C, C, F] => Command::Clip,
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Command::decode(&"PIPIIIC".into()), Some(Command::AddColor(BLACK)));
+        assert_eq!(Command::decode(&"PIPIIPF".into()), Some(Command::AddColor(TRANSPARENT)));
+        assert_eq!(Command::decode(&"PFFICCP".into()), Some(Command::Line));
+        assert_eq!(Command::decode(&"PFFICCF".into()), Some(Command::Clip));
+        assert_eq!(Command::decode(&"PFFICCI".into()), None);
+        // Too short, as at the end of the DNA
+        assert_eq!(Command::decode(&"PFFICC".into()), None);
+    }
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn test_serde() {
+        let commands = vec![Command::AddColor(RED), Command::AddColor(OPAQUE), Command::Line];
+        let json = serde_json::to_string(&commands).unwrap();
+        assert_eq!(json, r#"[{"AddColor":{"Rgb":[255,0,0]}},{"AddColor":{"Alpha":255}},"Line"]"#);
+        assert_eq!(serde_json::from_str::<Vec<Command>>(&json).unwrap(), commands);
+    }
+}
@@ -0,0 +1,246 @@
+//! Search for prefixes by hill climbing. Prefixes are sequences of operations
+//! from a grammar of pieces of DNA known to be safe to combine, such as gene
+//! invocations, with numeric arguments that the search varies.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::dna::DNA;
+use crate::execute::asnat;
+
+/// Most operations in a candidate.
+const MAX_OPS: usize = 32;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Part {
+    Bases(DNA),
+    /// A number in the range, encoded as by `asnat`.
+    Number(RangeInclusive<usize>),
+}
+
+/// The operations a prefix is built from. The text form has one operation
+/// per line, as bases with numbers written as `{lo..hi}` ranges. Whitespace
+/// is ignored and `#` starts a comment line:
+///
+/// ```text
+/// # The example prefix of the specification
+/// IIPIFFCPICICIICPIICIPPPICIIC
+/// IIPIFFCPICFPPICIICCIICIPPP {0..255} IIC
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Grammar {
+    rules: Vec<Vec<Part>>,
+}
+
+/// The bases of `s`, part of `line`. Unlike `DNA::from`, anything but bases
+/// and whitespace is an error, so a typo can't change the prefix.
+fn parse_bases(s: &str, line: &str) -> Result<Part, String> {
+    s.parse().map(Part::Bases).map_err(|e| format!("{} in line {:?}", e, line))
+}
+
+impl Grammar {
+    pub fn parse(text: &str) -> Result<Grammar, String> {
+        let mut rules = vec![];
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = vec![];
+            let mut rest = line;
+            while let Some(open) = rest.find('{') {
+                let close = rest[open..]
+                    .find('}')
+                    .map(|i| open + i)
+                    .ok_or_else(|| format!("missing `}}` in line {:?}", line))?;
+                parts.push(parse_bases(&rest[..open], line)?);
+                let range = &rest[open + 1..close];
+                let bad = || format!("bad range {:?} in line {:?}", range, line);
+                let (lo, hi) = match range.find("..") {
+                    Some(i) => (&range[..i], &range[i + 2..]),
+                    None => return Err(bad()),
+                };
+                let lo: usize = lo.trim().parse().map_err(|_| bad())?;
+                let hi: usize = hi.trim().parse().map_err(|_| bad())?;
+                if lo > hi {
+                    return Err(bad());
+                }
+                parts.push(Part::Number(lo..=hi));
+                rest = &rest[close + 1..];
+            }
+            parts.push(parse_bases(rest, line)?);
+            parts.retain(|p| p != &Part::Bases(DNA::default()));
+            rules.push(parts);
+        }
+        if rules.is_empty() {
+            return Err("no operations in the grammar".to_string());
+        }
+        Ok(Grammar { rules })
+    }
+
+    fn random_op(&self, rng: &mut StdRng) -> Op {
+        let rule = rng.gen_range(0..self.rules.len());
+        let args = self.ranges(rule).map(|r| rng.gen_range(r.clone())).collect();
+        Op { rule, args }
+    }
+
+    fn ranges(&self, rule: usize) -> impl Iterator<Item = &RangeInclusive<usize>> {
+        self.rules[rule].iter().filter_map(|p| match p {
+            Part::Number(r) => Some(r),
+            Part::Bases(_) => None,
+        })
+    }
+
+    /// The prefix `candidate` stands for.
+    pub fn prefix(&self, candidate: &Candidate) -> DNA {
+        let mut prefix = DNA::default();
+        for op in &candidate.ops {
+            let mut args = op.args.iter();
+            for part in &self.rules[op.rule] {
+                match part {
+                    Part::Bases(d) => prefix.concat(d.clone()),
+                    Part::Number(_) => prefix.concat(asnat(*args.next().unwrap())),
+                }
+            }
+        }
+        prefix
+    }
+
+    /// A random small change to `candidate`.
+    pub fn mutate(&self, candidate: &Candidate, rng: &mut StdRng) -> Candidate {
+        let mut c = candidate.clone();
+        let len = c.ops.len();
+        match rng.gen_range(0..4) {
+            0 if len > 0 => {
+                c.ops.remove(rng.gen_range(0..len));
+            }
+            1 | 2 if len > 0 => {
+                let op = &mut c.ops[rng.gen_range(0..len)];
+                let ranges: Vec<_> = self.ranges(op.rule).cloned().collect();
+                if ranges.is_empty() {
+                    *op = self.random_op(rng);
+                } else {
+                    let i = rng.gen_range(0..ranges.len());
+                    let (lo, hi) = (*ranges[i].start(), *ranges[i].end());
+                    op.args[i] = if rng.gen() {
+                        rng.gen_range(lo..=hi)
+                    } else {
+                        // A nudge, by up to a sixteenth of the range
+                        let step = rng.gen_range(1..=(hi - lo) / 16 + 1);
+                        if rng.gen() {
+                            op.args[i].saturating_add(step).min(hi)
+                        } else {
+                            op.args[i].saturating_sub(step).max(lo)
+                        }
+                    };
+                }
+            }
+            _ if len < MAX_OPS => {
+                let op = self.random_op(rng);
+                c.ops.insert(rng.gen_range(0..=len), op);
+            }
+            _ => {
+                c.ops[rng.gen_range(0..len)] = self.random_op(rng);
+            }
+        }
+        c
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Op {
+    rule: usize,
+    args: Vec<usize>,
+}
+
+/// A prefix, as the operations of a `Grammar` it is made of.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Candidate {
+    ops: Vec<Op>,
+}
+
+/// Improves on the empty prefix for `iterations` rounds, trying a mutation of
+/// the best candidate in each. Lower scores are better, and a candidate
+/// scoring as well as the best replaces it so the search can cross plateaus.
+/// Every improvement is logged to `log`. The same `seed` gives the same search.
+pub fn hill_climb(
+    grammar: &Grammar,
+    seed: u64,
+    iterations: u64,
+    mut score: impl FnMut(&DNA) -> usize,
+    log: &mut dyn Write,
+) -> io::Result<(Candidate, usize)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut best = Candidate::default();
+    let mut best_score = score(&grammar.prefix(&best));
+    writeln!(log, "seed {}, empty prefix scores {}", seed, best_score)?;
+    for i in 1..=iterations {
+        let candidate = grammar.mutate(&best, &mut rng);
+        let prefix = grammar.prefix(&candidate);
+        let s = score(&prefix);
+        if s < best_score {
+            writeln!(log, "iteration {} scores {} with prefix {:?}", i, s, prefix)?;
+        }
+        if s <= best_score {
+            best = candidate;
+            best_score = s;
+        }
+    }
+    Ok((best, best_score))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grammar() {
+        let g = Grammar::parse("# comment\n\nIIC\nIP {2..3} I {0..0}\n").unwrap();
+        assert_eq!(g.rules.len(), 2);
+        let c = Candidate {
+            ops: vec![Op { rule: 1, args: vec![2, 0] }, Op { rule: 0, args: vec![] }],
+        };
+        assert_eq!(g.prefix(&c), "IP ICP I P IIC".into());
+
+        assert!(Grammar::parse("").is_err());
+        assert!(Grammar::parse("I{2..1}").is_err());
+        assert!(Grammar::parse("I{2..").is_err());
+        assert!(Grammar::parse("IIPX{0..9}").is_err());
+        assert!(Grammar::parse("{0..9} IC-P").is_err());
+    }
+
+    #[test]
+    fn test_mutate_in_range() {
+        let g = Grammar::parse("C {10..20}\nF").unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut c = Candidate::default();
+        for _ in 0..1000 {
+            c = g.mutate(&c, &mut rng);
+            assert!(c.ops.len() <= MAX_OPS);
+            for op in &c.ops {
+                if op.rule == 0 {
+                    assert!((10..=20).contains(&op.args[0]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hill_climb() {
+        // Aim for a prefix of exactly 30 bases
+        let g = Grammar::parse("C {0..100}\nFF").unwrap();
+        let score = |d: &DNA| (d.len() as isize - 30).unsigned_abs();
+        let mut log = vec![];
+        let (best, s) = hill_climb(&g, 7, 500, score, &mut log).unwrap();
+        assert_eq!(s, 0);
+        assert_eq!(g.prefix(&best).len(), 30);
+        assert!(String::from_utf8(log.clone()).unwrap().starts_with("seed 7, empty prefix scores 30\n"));
+
+        // Reproducible
+        let mut again = vec![];
+        assert_eq!(hill_climb(&g, 7, 500, score, &mut again).unwrap(), (best, s));
+        assert_eq!(again, log);
+    }
+}
@@ -0,0 +1,182 @@
+//! Statistics about the patterns and templates executed, for tuning the
+//! interpreter.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::dna::DNA;
+use crate::execute::{Match, Monitor, PItem, TItem};
+
+/// Counts of values, either exact or in power-of-two buckets.
+#[derive(Clone, Default, Debug)]
+struct Histogram {
+    log: bool,
+    counts: BTreeMap<usize, u64>,
+}
+
+impl Histogram {
+    /// Values are bucketed by their highest bit, so the keys are 0, 1, 2, 4, ...
+    fn log() -> Self {
+        Histogram { log: true, counts: BTreeMap::new() }
+    }
+
+    fn exact() -> Self {
+        Histogram { log: false, counts: BTreeMap::new() }
+    }
+
+    fn add(&mut self, value: usize) {
+        let key = if self.log && value > 0 {
+            1 << (usize::BITS - 1 - value.leading_zeros())
+        } else {
+            value
+        };
+        *self.counts.entry(key).or_insert(0) += 1;
+    }
+
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "{{")?;
+        for (i, (key, count)) in self.counts.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(w, "{}\"{}\": {}", sep, key, count)?;
+        }
+        write!(w, "}}")
+    }
+}
+
+/// A `Monitor` collecting statistics, written out with `write_json`.
+pub struct Stats {
+    sample_interval: u64,
+    steps: u64,
+    matches: u64,
+    pattern_items: Histogram,
+    search_lengths: Histogram,
+    skip_sizes: Histogram,
+    template_items: Histogram,
+    protection_levels: Histogram,
+    replacement_lengths: Histogram,
+    /// `(step, length)` pairs, sampled every `sample_interval` steps.
+    dna_lengths: Vec<(u64, usize)>,
+}
+
+impl Stats {
+    /// Collects statistics, sampling the DNA length every `sample_interval`
+    /// steps.
+    pub fn new(sample_interval: u64) -> Self {
+        Stats {
+            sample_interval: sample_interval.max(1),
+            steps: 0,
+            matches: 0,
+            pattern_items: Histogram::log(),
+            search_lengths: Histogram::log(),
+            skip_sizes: Histogram::log(),
+            template_items: Histogram::log(),
+            protection_levels: Histogram::exact(),
+            replacement_lengths: Histogram::log(),
+            dna_lengths: vec![],
+        }
+    }
+
+    pub fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        let rate = if self.steps == 0 {
+            0.0
+        } else {
+            self.matches as f64 / self.steps as f64
+        };
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"steps\": {},", self.steps)?;
+        writeln!(w, "  \"matches\": {},", self.matches)?;
+        writeln!(w, "  \"match_rate\": {},", rate)?;
+        let histograms = [
+            ("pattern_items", &self.pattern_items),
+            ("search_lengths", &self.search_lengths),
+            ("skip_sizes", &self.skip_sizes),
+            ("template_items", &self.template_items),
+            ("protection_levels", &self.protection_levels),
+            ("replacement_lengths", &self.replacement_lengths),
+        ];
+        for (name, h) in histograms.iter() {
+            write!(w, "  \"{}\": ", name)?;
+            h.write_json(w)?;
+            writeln!(w, ",")?;
+        }
+        write!(w, "  \"dna_lengths\": [")?;
+        for (i, (step, len)) in self.dna_lengths.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(w, "{}[{}, {}]", sep, step, len)?;
+        }
+        writeln!(w, "]")?;
+        writeln!(w, "}}")
+    }
+}
+
+impl Monitor for Stats {
+    fn begin_step(&mut self, dna: &DNA) {
+        if self.steps.is_multiple_of(self.sample_interval) {
+            self.dna_lengths.push((self.steps, dna.len()));
+        }
+    }
+
+    fn decoded(&mut self, pattern: &[PItem], template: &[TItem], _len: usize) {
+        self.pattern_items.add(pattern.len());
+        for p in pattern {
+            match p {
+                PItem::Skip(n) => self.skip_sizes.add(*n),
+                PItem::Search(s) => self.search_lengths.add(s.len()),
+                _ => (),
+            }
+        }
+        self.template_items.add(template.len());
+        for t in template {
+            if let TItem::Ref { l, .. } = t {
+                self.protection_levels.add(*l);
+            }
+        }
+    }
+
+    fn matched(&mut self, _dna: &DNA, m: Option<&Match>) {
+        if m.is_some() {
+            self.matches += 1;
+        }
+    }
+
+    fn replaced(&mut self, _template: &[TItem], len: usize) {
+        self.replacement_lengths.add(len);
+    }
+
+    fn end_step(&mut self, _dna: &DNA) {
+        self.steps += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut h = Histogram::log();
+        for v in &[0, 1, 2, 3, 4, 7, 8, 1000] {
+            h.add(*v);
+        }
+        let mut out = vec![];
+        h.write_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"0": 1, "1": 1, "2": 2, "4": 2, "8": 1, "512": 1}"#
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        // First example of figure 16 in the spec, leaving the DNA `PICFC`
+        // which fails to decode
+        let mut stats = Stats::new(1);
+        let dna: DNA = "IIPIPICPIICICIIFICCIFPPIICCFPC".into();
+        crate::execute::execute_monitored(dna, |_| (), &mut stats);
+        assert_eq!(stats.steps, 1);
+        assert_eq!(stats.matches, 1);
+        assert_eq!(stats.skip_sizes.counts.get(&2), Some(&1));
+        assert_eq!(stats.protection_levels.counts.get(&0), Some(&1));
+        assert_eq!(stats.dna_lengths, vec![(0, 30), (1, 5)]);
+    }
+}
@@ -0,0 +1,84 @@
+//! A step-by-step log of an execution, with the instructions in the text form
+//! of `asm`.
+
+use std::io::Write;
+
+use crate::asm::{pattern_text, template_text};
+use crate::dna::DNA;
+use crate::execute::{Match, Monitor, PItem, TItem};
+
+/// A `Monitor` writing what each step does to `out`.
+pub struct Trace<W: Write> {
+    out: W,
+    steps: u64,
+}
+
+impl<W: Write> Trace<W> {
+    pub fn new(out: W) -> Self {
+        Trace { out, steps: 0 }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+// A trace that can't be written is no reason to stop, so errors are ignored
+impl<W: Write> Monitor for Trace<W> {
+    fn begin_step(&mut self, dna: &DNA) {
+        let _ = writeln!(self.out, "step {}, {} bases", self.steps, dna.len());
+    }
+
+    fn rna(&mut self, rna: &DNA) {
+        let _ = writeln!(self.out, "  rna {}", rna);
+    }
+
+    fn decoded(&mut self, pattern: &[PItem], _template: &[TItem], len: usize) {
+        let line = format!("  {} bases of code, pattern {}", len, pattern_text(pattern));
+        let _ = writeln!(self.out, "{}", line.trim_end());
+    }
+
+    fn matched(&mut self, _dna: &DNA, m: Option<&Match>) {
+        let _ = match m {
+            Some(m) => {
+                let groups: Vec<_> = m.env.iter().map(|(start, end)| format!("{}..{}", start, end)).collect();
+                writeln!(self.out, "  matched {} bases, groups [{}]", m.len, groups.join(", "))
+            }
+            None => writeln!(self.out, "  no match"),
+        };
+    }
+
+    fn replaced(&mut self, template: &[TItem], len: usize) {
+        let line = format!("  {} bases from template {}", len, template_text(template));
+        let _ = writeln!(self.out, "{}", line.trim_end());
+    }
+
+    fn end_step(&mut self, _dna: &DNA) {
+        self.steps += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::execute::execute_monitored;
+
+    #[test]
+    fn test_trace() {
+        let mut trace = Trace::new(vec![]);
+        let mut program = assemble("rna(PIPIIIC) ( !2 ) => |0| $0^1").unwrap();
+        program.concat("ICFP".into());
+        execute_monitored(program, |_| (), &mut trace);
+        let out = String::from_utf8(trace.into_inner()).unwrap();
+        assert_eq!(
+            out,
+            "step 0, 40 bases\n\
+             \x20 rna PIPIIIC\n\
+             \x20 36 bases of code, pattern ( !2 )\n\
+             \x20 matched 2 bases, groups [0..2]\n\
+             \x20 5 bases from template |0| $0^1\n\
+             step 1, 7 bases\n"
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! A web page for watching a long run from a browser on the same machine. The
+//! `Viewer` monitor publishes progress to a `Shared` state, which `serve`
+//! answers requests from on another thread:
+//!
+//! - `/` is a page polling the others,
+//! - `/status.json` has the counters and the most recent RNA,
+//! - `/image.png` is the bitmap being drawn on, as of the last snapshot.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dna::DNA;
+use crate::execute::Monitor;
+use crate::render::Bitmap;
+use crate::rna::Command;
+
+/// Number of RNA commands listed on the page.
+const RECENT_RNA: usize = 32;
+
+/// How often the monitor publishes its counters.
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Default, Debug)]
+pub struct Status {
+    pub steps: u64,
+    pub dna_len: usize,
+    pub rna: u64,
+    /// The most recent RNA, oldest first, with its command if it has one.
+    pub recent: VecDeque<(DNA, Option<Command>)>,
+    pub elapsed: Duration,
+    pub finished: bool,
+}
+
+impl Status {
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"steps\": {},", self.steps)?;
+        writeln!(w, "  \"dna_len\": {},", self.dna_len)?;
+        writeln!(w, "  \"rna\": {},", self.rna)?;
+        writeln!(w, "  \"elapsed\": {:.3},", self.elapsed.as_secs_f64())?;
+        writeln!(w, "  \"finished\": {},", self.finished)?;
+        write!(w, "  \"recent\": [")?;
+        for (i, (rna, command)) in self.recent.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            let command = command.map_or("null".to_string(), |c| format!("\"{:?}\"", c));
+            write!(w, "{}{{\"rna\": \"{:?}\", \"command\": {}}}", sep, rna, command)?;
+        }
+        writeln!(w, "]")?;
+        writeln!(w, "}}")
+    }
+}
+
+/// What the server shows.
+#[derive(Default)]
+pub struct Shared {
+    pub status: Mutex<Status>,
+    /// Pass to `Pipeline::start_with_snapshots` to fill in.
+    pub image: Arc<Mutex<Option<Bitmap>>>,
+}
+
+/// A `Monitor` publishing the progress of the run to `Shared`.
+pub struct Viewer {
+    shared: Arc<Shared>,
+    status: Status,
+    start: Instant,
+    last_publish: Instant,
+}
+
+impl Viewer {
+    pub fn new(shared: Arc<Shared>) -> Self {
+        let now = Instant::now();
+        Viewer { shared, status: Status::default(), start: now, last_publish: now }
+    }
+
+    fn publish(&mut self) {
+        self.status.elapsed = self.start.elapsed();
+        *self.shared.status.lock().unwrap() = self.status.clone();
+        self.last_publish = Instant::now();
+    }
+
+    /// Publishes the final counters, and `image` as the final image.
+    pub fn finish(mut self, image: &Bitmap) {
+        self.status.finished = true;
+        self.publish();
+        *self.shared.image.lock().unwrap() = Some(image.clone());
+    }
+}
+
+impl Monitor for Viewer {
+    fn rna(&mut self, rna: &DNA) {
+        self.status.rna += 1;
+        if self.status.recent.len() == RECENT_RNA {
+            self.status.recent.pop_front();
+        }
+        self.status.recent.push_back((rna.clone(), Command::decode(rna)));
+    }
+
+    fn end_step(&mut self, dna: &DNA) {
+        self.status.steps += 1;
+        self.status.dna_len = dna.len();
+        if self.last_publish.elapsed() >= PUBLISH_INTERVAL {
+            self.publish();
+        }
+    }
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>Endo</title></head>
+<body style="display: flex; gap: 2em; font-family: monospace">
+<img id="image" src="/image.png" width="600" height="600" style="border: 1px solid gray">
+<pre id="status">Waiting for the first update</pre>
+<script>
+async function update() {
+  const status = await (await fetch("/status.json")).json();
+  const recent = status.recent.map(r => r.rna + " " + (r.command || "")).reverse();
+  document.getElementById("status").textContent =
+    `step ${status.steps}${status.finished ? " (finished)" : ""}\n` +
+    `DNA length ${status.dna_len}\n${status.rna} RNA in ${status.elapsed} s\n\n` +
+    recent.join("\n");
+  document.getElementById("image").src = "/image.png?" + Date.now();
+  if (!status.finished) setTimeout(update, 1000);
+}
+update();
+</script>
+</body></html>
+"#;
+
+/// Answers one HTTP request read from `request`.
+fn respond(request: impl BufRead, out: &mut dyn Write, shared: &Shared) -> io::Result<()> {
+    let mut lines = request.lines();
+    let request_line = lines.next().transpose()?.unwrap_or_default();
+    // The headers are not needed, but must be read before answering
+    for line in lines {
+        if line?.is_empty() {
+            break;
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next().map(|p| p.split('?').next().unwrap()));
+
+    let mut body = vec![];
+    let (status, content_type) = match (method, path) {
+        (Some("GET"), Some("/")) => {
+            body.extend(PAGE.as_bytes());
+            ("200 OK", "text/html; charset=utf-8")
+        }
+        (Some("GET"), Some("/status.json")) => {
+            let status = shared.status.lock().unwrap().clone();
+            status.write_json(&mut body)?;
+            ("200 OK", "application/json")
+        }
+        (Some("GET"), Some("/image.png")) => {
+            let image = shared.image.lock().unwrap().clone().unwrap_or_default();
+            image.write_png(&mut body)?;
+            ("200 OK", "image/png")
+        }
+        (Some("GET"), _) => ("404 Not Found", "text/plain"),
+        _ => ("405 Method Not Allowed", "text/plain"),
+    };
+    write!(
+        out,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    out.write_all(&body)?;
+    out.flush()
+}
+
+/// Serves `shared` on `port` of localhost from a new thread, one request at a
+/// time. Only connections from the same machine are accepted.
+pub fn serve(port: u16, shared: Arc<Shared>) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            // A failed request is the browser's problem, not the run's
+            let _ = stream.and_then(|mut s| {
+                s.set_read_timeout(Some(Duration::from_secs(5)))?;
+                let request = BufReader::new(s.try_clone()?);
+                respond(request, &mut s, &shared)
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::execute::execute_monitored;
+
+    fn get(path: &str, shared: &Shared) -> String {
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        let mut out = vec![];
+        respond(request.as_bytes(), &mut out, shared).unwrap();
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    #[test]
+    fn test_viewer() {
+        let shared = Arc::new(Shared::default());
+        let mut viewer = Viewer::new(shared.clone());
+        // Two steps, emitting known and unknown RNA
+        execute_monitored("IIC III(PIPIIIP) IIC IIC III(IIIIIII) IIC".into(), |_| (), &mut viewer);
+        viewer.finish(&Bitmap::default());
+
+        let status = get("/status.json", &shared);
+        assert!(status.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(status.contains("\"steps\": 2,"));
+        assert!(status.contains("\"finished\": true,"));
+        assert!(status.contains(
+            "\"recent\": [{\"rna\": \"PIPIIIP\", \"command\": \"AddColor(Rgb(255, 0, 0))\"}, \
+             {\"rna\": \"IIIIIII\", \"command\": null}]"
+        ));
+
+        assert!(get("/image.png?123", &shared).contains("Content-Type: image/png"));
+        assert!(get("/", &shared).contains("<title>Endo</title>"));
+        assert!(get("/secret", &shared).starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Runs every case in `tests/corpus`. A case is a text file of `key: value`
+//! lines, where `#` starts a comment line:
+//!
+//! - `dna:` the initial DNA. Repeated lines are concatenated and anything but
+//!   the four bases is ignored.
+//! - `steps:` how many iterations to run, or `all` to run until finished.
+//! - `expect_dna:` the DNA after running. Optional.
+//! - `rna:` one line per RNA command expected, in order. Omitting these lines
+//!   means no RNA is expected.
+//! - `image:` the FNV-1a hash of the red, green and blue bytes of the pixels
+//!   of the image the RNA renders to, in hexadecimal. Optional.
+//! - `pixel:` `X Y RRGGBB`, the color of one pixel of that image. Repeated
+//!   lines check more pixels.
+//!
+//! The DNA and RNA expectations come from the examples and rules of the
+//! specification. The image hashes of the `render_*` cases were recorded from
+//! this renderer, so they only catch changes in its behavior, not mistakes it
+//! already made. The `pixel:` lines were worked out from the specification
+//! and are what checks the renderer against it.
+
+use std::fs;
+use std::path::Path;
+
+use endo::dna::DNA;
+use endo::execute::step;
+use endo::render::{Bitmap, Renderer};
+use endo::rna::Command;
+
+#[derive(Default)]
+struct Case {
+    dna: String,
+    steps: Option<usize>,
+    expect_dna: Option<DNA>,
+    rna: Vec<DNA>,
+    image: Option<u64>,
+    pixels: Vec<(usize, usize, u32)>,
+}
+
+fn parse_pixel(value: &str) -> Result<(usize, usize, u32), String> {
+    let bad = || format!("bad pixel {:?}", value);
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    match fields.as_slice() {
+        [x, y, rgb] => Ok((
+            x.parse().map_err(|_| bad())?,
+            y.parse().map_err(|_| bad())?,
+            u32::from_str_radix(rgb, 16).map_err(|_| bad())?,
+        )),
+        _ => Err(bad()),
+    }
+}
+
+/// The hash of the colors of the final image, which has no transparency.
+fn hash(image: &Bitmap) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for p in image.pixels() {
+        for &b in &[p.r, p.g, p.b] {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+fn parse(text: &str) -> Result<Case, String> {
+    let mut case = Case::default();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.find(':') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => return Err(format!("missing `:` in line {:?}", line)),
+        };
+        match key {
+            "dna" => case.dna.push_str(value),
+            "steps" if value == "all" => case.steps = None,
+            "steps" => {
+                let n = value.parse().map_err(|e| format!("bad steps: {}", e))?;
+                case.steps = Some(n);
+            }
+            "expect_dna" => case.expect_dna = Some(value.into()),
+            "rna" => case.rna.push(value.into()),
+            "image" => {
+                let hash = u64::from_str_radix(value, 16).map_err(|e| format!("bad image: {}", e))?;
+                case.image = Some(hash);
+            }
+            "pixel" => case.pixels.push(parse_pixel(value)?),
+            _ => return Err(format!("unknown key {:?}", key)),
+        }
+    }
+    Ok(case)
+}
+
+fn run(case: &Case) -> Result<(), String> {
+    let mut dna: DNA = case.dna.as_str().into();
+    let mut rna = vec![];
+    let mut steps = 0;
+    while case.steps.is_none_or(|n| steps < n) {
+        if step(&mut dna, &mut |chunk| rna.push(chunk), &mut ()).is_err() {
+            break;
+        }
+        steps += 1;
+    }
+    if let Some(expected) = &case.expect_dna {
+        if &dna != expected {
+            return Err(format!("DNA is {:?}, expected {:?}", dna, expected));
+        }
+    }
+    if rna != case.rna {
+        return Err(format!("RNA is {:?}, expected {:?}", rna, case.rna));
+    }
+    if case.image.is_none() && case.pixels.is_empty() {
+        return Ok(());
+    }
+    let mut renderer = Renderer::default();
+    rna.iter().filter_map(Command::decode).for_each(|c| renderer.apply(c));
+    let image = renderer.finish();
+    for &(x, y, expected) in &case.pixels {
+        let p = image.get(x, y);
+        let rgb = (p.r as u32) << 16 | (p.g as u32) << 8 | p.b as u32;
+        if rgb != expected {
+            return Err(format!("pixel {} {} is {:06x}, expected {:06x}", x, y, rgb, expected));
+        }
+    }
+    if let Some(expected) = case.image {
+        if hash(&image) != expected {
+            return Err(format!("image hash is {:016x}, expected {:016x}", hash(&image), expected));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .expect("Unable to read the corpus")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No cases in {}", dir.display());
+
+    let mut failures = vec![];
+    for path in &paths {
+        let text = fs::read_to_string(path).unwrap();
+        if let Err(e) = parse(&text).and_then(|case| run(&case)) {
+            failures.push(format!("{}: {}", path.display(), e));
+        }
+    }
+    assert!(failures.is_empty(), "Failed cases:\n{}", failures.join("\n"));
+}